@@ -31,17 +31,12 @@ impl AstPrinter {
     }
 }
 
-impl Visitor<String> for AstPrinter {
-    fn visit_binary_expr(
-        &mut self,
-        left: &Box<Expr>,
-        operator: &Token,
-        right: &Box<Expr>,
-    ) -> String {
-        self.parenthesize(&operator.lexeme, &[left, right])
+impl Visitor<'_, String> for AstPrinter {
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(operator.lexeme, &[left, right])
     }
 
-    fn visit_grouping_expr(&mut self, expression: &Box<Expr>) -> String {
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> String {
         self.parenthesize("group", &[expression])
     }
 
@@ -49,12 +44,177 @@ impl Visitor<String> for AstPrinter {
         match value {
             Literal::Nil => "nil".to_string(),
             Literal::Number(n) => n.to_string(),
-            Literal::String(s) => s.clone(),
+            Literal::String(s) => s.to_string(),
             Literal::Bool(b) => b.to_string(),
         }
     }
 
-    fn visit_unary_expr(&mut self, operator: &Token, right: &Box<Expr>) -> String {
-        self.parenthesize(&operator.lexeme, &[right])
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(operator.lexeme, &[right])
+    }
+
+    fn visit_ternary_expr(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> String {
+        self.parenthesize("?:", &[condition, then_branch, else_branch])
+    }
+
+    fn visit_coalesce_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(operator.lexeme, &[left, right])
+    }
+
+    fn visit_range_expr(&mut self, start: &Expr, operator: &Token, end: &Expr) -> String {
+        self.parenthesize(operator.lexeme, &[start, end])
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        let elements: Vec<&Expr> = elements.iter().collect();
+        self.parenthesize("list", &elements)
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> String {
+        let exprs: Vec<&Expr> = entries
+            .iter()
+            .flat_map(|(key, value)| [key, value])
+            .collect();
+        self.parenthesize("map", &exprs)
+    }
+
+    fn visit_index_expr(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+        self.parenthesize("index", &[object, index])
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+    ) -> String {
+        let nil = Expr::new_literal(Literal::Nil);
+        self.parenthesize(
+            "slice",
+            &[object, start.unwrap_or(&nil), end.unwrap_or(&nil)],
+        )
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        value: &Expr,
+    ) -> String {
+        self.parenthesize(
+            &format!("index{}", operator.lexeme),
+            &[object, index, value],
+        )
+    }
+
+    fn visit_increment_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        prefix: bool,
+    ) -> String {
+        let name = if prefix {
+            format!("{}index", operator.lexeme)
+        } else {
+            format!("index{}", operator.lexeme)
+        };
+        self.parenthesize(&name, &[object, index])
+    }
+
+    fn visit_invoke_expr(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+        arguments: &[Expr],
+        optional: bool,
+    ) -> String {
+        let mut exprs = vec![object];
+        exprs.extend(arguments);
+        let dot = if optional { "?." } else { "." };
+        self.parenthesize(&format!("{dot}{}", name.lexeme), &exprs)
+    }
+
+    fn visit_throw_expr(&mut self, _keyword: &Token, value: &Expr) -> String {
+        self.parenthesize("throw", &[value])
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token, _slot: usize) -> String {
+        name.lexeme.to_string()
+    }
+
+    fn visit_block_expr(&mut self, _brace: &Token, expressions: &[Expr]) -> String {
+        let expressions: Vec<&Expr> = expressions.iter().collect();
+        self.parenthesize("block", &expressions)
+    }
+
+    fn visit_for_expr(
+        &mut self,
+        label: Option<&Token>,
+        _keyword: &Token,
+        variable: &Token,
+        iterable: &Expr,
+        body: &Expr,
+        _collect: bool,
+    ) -> String {
+        let name = match label {
+            Some(label) => format!("{}: for {}", label.lexeme, variable.lexeme),
+            None => format!("for {}", variable.lexeme),
+        };
+        self.parenthesize(&name, &[iterable, body])
+    }
+
+    fn visit_break_expr(
+        &mut self,
+        keyword: &Token,
+        label: Option<&Token>,
+        _loops: usize,
+    ) -> String {
+        loop_control(keyword, label)
+    }
+
+    fn visit_continue_expr(
+        &mut self,
+        keyword: &Token,
+        label: Option<&Token>,
+        _loops: usize,
+    ) -> String {
+        loop_control(keyword, label)
+    }
+
+    fn visit_try_expr(
+        &mut self,
+        _keyword: &Token,
+        body: &Expr,
+        handler: Option<(&Token, &Expr)>,
+        finally: Option<&Expr>,
+    ) -> String {
+        let mut buffer = format!("(try {}", body.accept(self));
+        if let Some((variable, handler)) = handler {
+            let name = format!("catch {}", variable.lexeme);
+            buffer.push_str(&format!(" {}", self.parenthesize(&name, &[handler])));
+        }
+        if let Some(finally) = finally {
+            buffer.push_str(&format!(" {}", self.parenthesize("finally", &[finally])));
+        }
+        buffer.push(')');
+
+        buffer
+    }
+}
+
+fn loop_control(keyword: &Token, label: Option<&Token>) -> String {
+    match label {
+        Some(label) => format!("({} {})", keyword.lexeme, label.lexeme),
+        None => format!("({})", keyword.lexeme),
     }
 }
@@ -0,0 +1,242 @@
+use crate::{
+    chunk::Chunk,
+    err::{ErrorCode, Span, StrixError},
+    interner::Interner,
+    messages::{Msg, tr},
+    value::Value,
+    verifier,
+};
+
+pub const MAGIC: [u8; 4] = *b"STRX";
+/// Bumped whenever the format or the meaning of any opcode changes.
+pub const VERSION: u16 = 5;
+/// The file extension of compiled scripts.
+pub const EXTENSION: &str = "strixc";
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+
+/// The name of the prototype compiled from a script's top level.
+const SCRIPT: &str = "<script>";
+
+/// Encodes a compiled script as a `.strixc` file. All integers are big-endian.
+///
+/// ```text
+/// magic       "STRX"
+/// version     u16
+/// constants   u32 count, each a u8 tag followed by
+///               0: a number, as its f64 bits in a u64
+///               1: a string, as a u32 length and UTF-8 bytes
+/// prototypes  u32 count, each
+///               name      u32 length and UTF-8 bytes
+///               arity     u8
+///               code      u32 length and bytes
+///               spans     u32 count, each the offset, start, end, line and column as u32s
+/// checksum    u32 CRC-32 of everything before it
+/// ```
+///
+/// Until the language has functions, a file holds exactly one prototype: the script itself.
+pub fn write(chunk: &Chunk) -> Vec<u8> {
+    let mut out = Writer(MAGIC.to_vec());
+    out.u16(VERSION);
+
+    out.u32(chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Value::Number(n) => {
+                out.u8(NUMBER);
+                out.0.extend(n.to_bits().to_be_bytes());
+            }
+            Value::String(s) => {
+                out.u8(STRING);
+                out.bytes(s.as_bytes());
+            }
+            value => unreachable!("{} constants are never compiled", value.type_name()),
+        }
+    }
+
+    out.u32(1);
+    out.bytes(SCRIPT.as_bytes());
+    out.u8(0);
+    out.bytes(&chunk.code);
+    out.u32(chunk.spans.len());
+    for (offset, span) in &chunk.spans {
+        for n in [*offset, span.start, span.end, span.line, span.column] {
+            out.u32(n);
+        }
+    }
+
+    let checksum = crc32(&out.0);
+    out.0.extend(checksum.to_be_bytes());
+    out.0
+}
+
+/// Decodes and verifies a `.strixc` file read from `file`, rejecting foreign, mismatched,
+/// corrupt and invalid ones.
+pub fn read(bytes: &[u8], file: &str) -> Result<Chunk, StrixError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(StrixError::new(
+            ErrorCode::NotBytecode,
+            tr(Msg::NotBytecode, &[&file]),
+        ));
+    }
+
+    let mut input = Reader {
+        bytes,
+        pos: MAGIC.len(),
+        file,
+    };
+    let version = input.u16()?;
+    if version != VERSION {
+        return Err(StrixError::new(
+            ErrorCode::BytecodeVersion,
+            tr(Msg::BytecodeVersion, &[&file, &version, &VERSION]),
+        ));
+    }
+
+    let Some(body) = bytes.len().checked_sub(4) else {
+        return Err(input.corrupt());
+    };
+    let checksum = u32::from_be_bytes([
+        bytes[body],
+        bytes[body + 1],
+        bytes[body + 2],
+        bytes[body + 3],
+    ]);
+    if checksum != crc32(&bytes[..body]) {
+        return Err(StrixError::new(
+            ErrorCode::CorruptBytecode,
+            tr(Msg::ChecksumMismatch, &[&file]),
+        ));
+    }
+    input.bytes = &bytes[..body];
+
+    let mut chunk = Chunk::new();
+    let mut strings = Interner::new();
+    for _ in 0..input.u32()? {
+        let constant = match input.u8()? {
+            NUMBER => Value::Number(f64::from_bits(input.u64()?)),
+            STRING => Value::String(strings.share(&input.string()?)),
+            _ => return Err(input.corrupt()),
+        };
+        chunk.add_constant(constant);
+    }
+
+    if input.u32()? != 1 {
+        return Err(input.corrupt());
+    }
+    input.string()?;
+    input.u8()?;
+    chunk.code = input.bytes()?.to_vec();
+    for _ in 0..input.u32()? {
+        let offset = input.u32()? as usize;
+        let [start, end, line, column] =
+            [input.u32()?, input.u32()?, input.u32()?, input.u32()?].map(|n| n as usize);
+        chunk
+            .spans
+            .push((offset, Span::new(start, end, line, column)));
+    }
+
+    if input.pos != input.bytes.len() {
+        return Err(input.corrupt());
+    }
+    verifier::verify(&chunk, file)?;
+    Ok(chunk)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn u16(&mut self, n: u16) {
+        self.0.extend(n.to_be_bytes());
+    }
+
+    fn u32(&mut self, n: usize) {
+        // Chunks are far smaller than 4 GiB, as their operands are at most 16 bits wide.
+        self.0.extend((n as u32).to_be_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len());
+        self.0.extend(bytes);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    file: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    fn corrupt(&self) -> StrixError {
+        StrixError::new(
+            ErrorCode::CorruptBytecode,
+            tr(Msg::TruncatedBytecode, &[&self.file, &self.pos]),
+        )
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], StrixError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| self.corrupt())?;
+        self.pos += N;
+        Ok(bytes.try_into().expect("the slice has N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, StrixError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StrixError> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StrixError> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StrixError> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], StrixError> {
+        let len = self.u32()? as usize;
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| self.corrupt())?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, StrixError> {
+        let start = self.pos;
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| {
+            self.pos = start;
+            self.corrupt()
+        })
+    }
+}
+
+/// The CRC-32 checksum used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
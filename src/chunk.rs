@@ -0,0 +1,265 @@
+use crate::{err::Span, tokenizer::TokenType, value::Value};
+
+/// A bytecode instruction. Operands follow the opcode byte, with 16-bit operands stored
+/// big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Pushes the constant at the 16-bit index.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Negate,
+    Not,
+
+    /// Jumps forward by the 16-bit offset.
+    Jump,
+    /// Jumps forward by the 16-bit offset if the top of the stack is falsey, without popping it.
+    JumpIfFalse,
+    /// Jumps forward by the 16-bit offset if the top of the stack is not nil, without popping it.
+    JumpIfNotNil,
+    /// Jumps forward by the 16-bit offset if the top of the stack is nil, without popping it.
+    JumpIfNil,
+    /// Jumps backward by the 16-bit offset.
+    Loop,
+
+    Range,
+    RangeInclusive,
+    /// Collects the 16-bit count of values on top of the stack into a list.
+    List,
+    /// Collects the 16-bit count of key and value pairs on top of the stack into a map.
+    Map,
+    Index,
+    /// Slices the list below two bounds, where nil stands for a missing bound.
+    Slice,
+    /// Stores into an indexed element. The 8-bit operand is a position in `ASSIGN_OPERATORS`.
+    SetIndex,
+    /// Increments or decrements an indexed element. The 8-bit operand holds `DECREMENT` and
+    /// `PREFIX` flags.
+    IncrementIndex,
+    /// Calls the method named by the constant at the 16-bit index, with the 8-bit count of
+    /// arguments on top of the stack.
+    Invoke,
+    /// Pushes a copy of the local variable at the 16-bit slot.
+    GetLocal,
+    /// Moves the top of the stack into a new local variable.
+    PushLocal,
+    /// Drops the last local variable.
+    PopLocal,
+    /// Replaces the value on top of the stack with an iterator over it.
+    Iter,
+    /// Pushes the next value of the iterator in the local at the first 16-bit operand, or
+    /// jumps forward by the second one once it has none left.
+    ForNext,
+    /// Pops a value and appends it to the list below it.
+    Append,
+    /// Starts handling errors with the code forward by the 16-bit offset, until the matching
+    /// `EndTry`. An error drops what the stack and the locals gained since, then pushes the
+    /// error and jumps there.
+    Try,
+    /// Stops handling errors with the innermost handler.
+    EndTry,
+    /// Replaces the error on top of the stack with the value a `catch` clause receives for it.
+    Caught,
+    Throw,
+    Return,
+}
+
+impl OpCode {
+    /// Every opcode, in the order of their byte values.
+    const ALL: [OpCode; 48] = [
+        Self::Constant,
+        Self::Nil,
+        Self::True,
+        Self::False,
+        Self::Pop,
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Modulo,
+        Self::BitAnd,
+        Self::BitOr,
+        Self::BitXor,
+        Self::ShiftLeft,
+        Self::ShiftRight,
+        Self::Greater,
+        Self::GreaterEqual,
+        Self::Less,
+        Self::LessEqual,
+        Self::Equal,
+        Self::NotEqual,
+        Self::Negate,
+        Self::Not,
+        Self::Jump,
+        Self::JumpIfFalse,
+        Self::JumpIfNotNil,
+        Self::JumpIfNil,
+        Self::Loop,
+        Self::Range,
+        Self::RangeInclusive,
+        Self::List,
+        Self::Map,
+        Self::Index,
+        Self::Slice,
+        Self::SetIndex,
+        Self::IncrementIndex,
+        Self::Invoke,
+        Self::GetLocal,
+        Self::PushLocal,
+        Self::PopLocal,
+        Self::Iter,
+        Self::ForNext,
+        Self::Append,
+        Self::Try,
+        Self::EndTry,
+        Self::Caught,
+        Self::Throw,
+        Self::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// How many values the instruction pops and then pushes, given the operand bytes that
+    /// follow it. `ForNext` only pushes when it falls through rather than jumping, a `Try`
+    /// handler starts with the error pushed, and `Throw` and `Return` do not continue at all.
+    pub fn stack_effect(&self, operands: &[u8]) -> (usize, usize) {
+        let u16_operand = || u16::from_be_bytes([operands[0], operands[1]]) as usize;
+
+        match self {
+            Self::Constant | Self::Nil | Self::True | Self::False | Self::GetLocal => (0, 1),
+            Self::Pop | Self::PushLocal | Self::Throw | Self::Return => (1, 0),
+            Self::Negate | Self::Not | Self::Iter | Self::Caught => (1, 1),
+            Self::Add
+            | Self::Subtract
+            | Self::Multiply
+            | Self::Divide
+            | Self::Modulo
+            | Self::BitAnd
+            | Self::BitOr
+            | Self::BitXor
+            | Self::ShiftLeft
+            | Self::ShiftRight
+            | Self::Greater
+            | Self::GreaterEqual
+            | Self::Less
+            | Self::LessEqual
+            | Self::Equal
+            | Self::NotEqual
+            | Self::Range
+            | Self::RangeInclusive
+            | Self::Index
+            | Self::IncrementIndex
+            | Self::Append => (2, 1),
+            Self::Jump | Self::Loop | Self::PopLocal | Self::Try | Self::EndTry => (0, 0),
+            Self::JumpIfFalse | Self::JumpIfNotNil | Self::JumpIfNil => (1, 1),
+            Self::ForNext => (0, 1),
+            Self::List => (u16_operand(), 1),
+            Self::Map => (u16_operand() * 2, 1),
+            Self::Slice | Self::SetIndex => (3, 1),
+            Self::Invoke => (operands[2] as usize + 1, 1),
+        }
+    }
+
+    /// How many operand bytes follow the opcode.
+    pub fn operand_len(&self) -> usize {
+        match self {
+            Self::Constant
+            | Self::Jump
+            | Self::JumpIfFalse
+            | Self::JumpIfNotNil
+            | Self::JumpIfNil
+            | Self::Loop
+            | Self::Try
+            | Self::List
+            | Self::Map
+            | Self::GetLocal => 2,
+            Self::SetIndex | Self::IncrementIndex => 1,
+            Self::Invoke => 3,
+            Self::ForNext => 4,
+            _ => 0,
+        }
+    }
+}
+
+/// The operators of `SetIndex`, in operand order.
+pub const ASSIGN_OPERATORS: [TokenType; 11] = [
+    TokenType::Equal,
+    TokenType::PlusEqual,
+    TokenType::MinusEqual,
+    TokenType::StarEqual,
+    TokenType::SlashEqual,
+    TokenType::PercentEqual,
+    TokenType::AmpersandEqual,
+    TokenType::PipeEqual,
+    TokenType::CaretEqual,
+    TokenType::LessLessEqual,
+    TokenType::GreaterGreaterEqual,
+];
+
+/// `IncrementIndex` flag for `--` rather than `++`.
+pub const DECREMENT: u8 = 1;
+/// `IncrementIndex` flag for the prefix form, which evaluates to the new value.
+pub const PREFIX: u8 = 2;
+
+/// A compiled script: its code, the constants it refers to and where each instruction came
+/// from in the source.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// Runs of code sharing a span, as the offset each run starts at. Sorted by offset.
+    pub spans: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, span: Span) {
+        if self.spans.last().is_none_or(|&(_, last)| last != span) {
+            self.spans.push((self.code.len(), span));
+        }
+        self.code.push(byte);
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// The span of the code at `offset`.
+    pub fn span_at(&self, offset: usize) -> Span {
+        let run = self.spans.partition_point(|&(start, _)| start <= offset);
+        match run {
+            0 => Span::new(0, 0, 1, 1),
+            run => self.spans[run - 1].1,
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
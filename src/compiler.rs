@@ -0,0 +1,817 @@
+use std::collections::HashMap;
+
+use crate::{
+    chunk::{ASSIGN_OPERATORS, Chunk, DECREMENT, OpCode, PREFIX},
+    err::{ErrorCode, Span, StrixError},
+    expr::Expr,
+    interner::{Interner, Symbol},
+    messages::{Msg, tr},
+    tokenizer::{Literal, Token, TokenType},
+    value::Value,
+    visitor::Visitor,
+};
+
+/// A constant as it is looked up for reuse. Numbers are keyed by their bits so that `0` and
+/// `-0` stay distinct, and strings by their symbol.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(Symbol),
+}
+
+/// A loop being compiled, for the `break` and `continue` expressions inside it.
+struct Loop {
+    /// Where each pass starts.
+    start: usize,
+    /// How many values are on the stack at the start of each pass.
+    depth: usize,
+    /// How many locals are in scope at the start of each pass.
+    locals: usize,
+    /// The jumps of the `break`s out of the loop, patched to its exit.
+    breaks: Vec<usize>,
+}
+
+/// A `try` expression being compiled, for the `break` and `continue` expressions that leave it.
+struct Try {
+    /// How many values were on the stack when it started.
+    depth: usize,
+    /// How many locals were in scope when it started.
+    locals: usize,
+    /// How many loops are around it.
+    loops: usize,
+    /// Whether one of its handlers is active, which leaving has to end.
+    handler: bool,
+    /// Whether it has a cleanup, which leaving has to run.
+    finally: bool,
+    /// The `break`s and `continue`s that leave through its cleanup, each as the keyword's
+    /// type and the index of its loop. Their position after `THREW` is how the try ended.
+    exits: Vec<(TokenType, usize)>,
+    /// The jumps of those exits, patched to the cleanup.
+    exit_jumps: Vec<usize>,
+}
+
+/// How a `try` with a finally clause ended, kept while its cleanup runs: it completed, it
+/// threw, or it left through the exit at the position past `THREW`.
+const COMPLETED: usize = 0;
+const THREW: usize = 1;
+
+/// Compiles a syntax tree into a chunk of bytecode for the `Vm`.
+pub struct Compiler {
+    chunk: Chunk,
+    constants: HashMap<ConstantKey, u16>,
+    /// String literals and method names, shared by every constant that uses them.
+    strings: Interner,
+    /// The span of the expression being compiled, given to the instructions it emits.
+    span: Span,
+    /// How many local variables the code being compiled has in scope.
+    locals: usize,
+    /// How many values the code compiled so far leaves on the stack.
+    depth: usize,
+    /// Where the last instruction starts, until its effect on `depth` is counted.
+    unsettled: Option<usize>,
+    /// The loops around the code being compiled, innermost last.
+    loops: Vec<Loop>,
+    /// The `try` expressions around the code being compiled, innermost last.
+    trys: Vec<Try>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            chunk: Chunk::new(),
+            constants: HashMap::new(),
+            strings: Interner::new(),
+            span: Span::new(0, 0, 1, 1),
+            locals: 0,
+            depth: 0,
+            unsettled: None,
+            loops: vec![],
+            trys: vec![],
+        }
+    }
+
+    pub fn compile(mut self, expression: &Expr) -> Result<Chunk, StrixError> {
+        self.compile_expr(expression)?;
+        self.emit(OpCode::Return);
+        Ok(self.chunk)
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), StrixError> {
+        expr.accept(self)
+    }
+
+    fn at(&mut self, token: &Token) -> &mut Self {
+        self.span = token.span;
+        self
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.settle();
+        self.unsettled = Some(self.chunk.code.len());
+        self.chunk.write(op as u8, self.span);
+    }
+
+    /// Counts the values the last instruction pops and pushes, now that its operands are
+    /// written.
+    fn settle(&mut self) {
+        let Some(offset) = self.unsettled.take() else {
+            return;
+        };
+        if let Some(op) = OpCode::from_byte(self.chunk.code[offset]) {
+            let (pops, pushes) = op.stack_effect(&self.chunk.code[offset + 1..]);
+            self.depth = self.depth.saturating_sub(pops) + pushes;
+        }
+    }
+
+    /// Compiles a `break` or `continue` aimed `loops` loops out. Nothing after it runs, but the
+    /// code that follows is compiled as if it had left a value, like any other expression.
+    fn loop_control(&mut self, keyword: &Token, loops: usize) -> Result<(), StrixError> {
+        self.at(keyword).settle();
+        let (depth, locals) = (self.depth, self.locals);
+        let Some(index) = self.loops.len().checked_sub(loops + 1) else {
+            unreachable!("the parser only accepts 'break' and 'continue' inside loops");
+        };
+
+        self.leave(keyword.token_type, index)?;
+        self.settle();
+        self.depth = depth + 1;
+        self.locals = locals;
+        Ok(())
+    }
+
+    /// Emits the code that leaves for the loop at `index` with a `break` or `continue`. It
+    /// ends the handlers of the `try` expressions inside that loop, up to the first with a
+    /// finally clause, which it jumps into with the exit as how the try ended, so that the
+    /// cleanup goes on leaving once done. Past them, it drops the values and locals that the
+    /// loop did not have at the start of a pass, then jumps to the loop's exit or next pass.
+    fn leave(&mut self, control: TokenType, index: usize) -> Result<(), StrixError> {
+        for position in (0..self.trys.len()).rev() {
+            let try_ = &self.trys[position];
+            if try_.loops <= index {
+                break;
+            }
+            let (depth, locals, handler) = (try_.depth, try_.locals, try_.handler);
+            if try_.finally {
+                self.unwind(depth, locals);
+            }
+            if handler {
+                self.emit(OpCode::EndTry);
+            }
+            if !self.trys[position].finally {
+                continue;
+            }
+
+            let exits = &mut self.trys[position].exits;
+            let exit = match exits.iter().position(|&exit| exit == (control, index)) {
+                Some(exit) => exit,
+                None => {
+                    exits.push((control, index));
+                    exits.len() - 1
+                }
+            };
+            self.emit(OpCode::Nil);
+            self.emit_kind(THREW + 1 + exit)?;
+            let jump = self.emit_jump(OpCode::Jump);
+            self.trys[position].exit_jumps.push(jump);
+            return Ok(());
+        }
+
+        let target = &self.loops[index];
+        let (start, target_depth, target_locals) = (target.start, target.depth, target.locals);
+        self.unwind(target_depth, target_locals);
+
+        if control == TokenType::Break {
+            let jump = self.emit_jump(OpCode::Jump);
+            self.loops[index].breaks.push(jump);
+        } else {
+            self.emit_loop(start)?;
+        }
+        Ok(())
+    }
+
+    /// Emits how a `try` with a finally clause ended.
+    fn emit_kind(&mut self, kind: usize) -> Result<(), StrixError> {
+        self.emit_constant(ConstantKey::Number((kind as f64).to_bits()))
+    }
+
+    /// Emits the code that drops values and locals until `depth` values are on the stack and
+    /// `locals` locals are in scope.
+    fn unwind(&mut self, depth: usize, locals: usize) {
+        self.settle();
+        for _ in depth..self.depth {
+            self.emit(OpCode::Pop);
+        }
+        for _ in locals..self.locals {
+            self.emit(OpCode::PopLocal);
+        }
+        self.locals = locals;
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.chunk.write(byte, self.span);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        for byte in value.to_be_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+
+    fn too_large(&self, msg: Msg, limit: usize) -> StrixError {
+        StrixError::new(ErrorCode::ChunkTooLarge, tr(msg, &[&limit])).with_span(self.span)
+    }
+
+    /// Converts a count into a 16-bit operand.
+    fn count(&self, count: usize, msg: Msg) -> Result<u16, StrixError> {
+        u16::try_from(count).map_err(|_| self.too_large(msg, u16::MAX as usize))
+    }
+
+    fn constant(&mut self, key: ConstantKey) -> Result<u16, StrixError> {
+        if let Some(&index) = self.constants.get(&key) {
+            return Ok(index);
+        }
+
+        let index = self.count(self.chunk.constants.len(), Msg::TooManyConstants)?;
+        self.chunk.add_constant(match &key {
+            ConstantKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            ConstantKey::String(symbol) => Value::String(self.strings.resolve(*symbol).clone()),
+        });
+        self.constants.insert(key, index);
+        Ok(index)
+    }
+
+    fn emit_constant(&mut self, key: ConstantKey) -> Result<(), StrixError> {
+        let index = self.constant(key)?;
+        self.emit(OpCode::Constant);
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    /// Emits a jump with a placeholder offset, returning where to patch it.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_jump_operand()
+    }
+
+    /// Emits the placeholder offset of a jump whose opcode was just emitted.
+    fn emit_jump_operand(&mut self) -> usize {
+        self.emit_u16(u16::MAX);
+        self.chunk.code.len() - 2
+    }
+
+    /// Emits a jump back to the instruction at `start`.
+    fn emit_loop(&mut self, start: usize) -> Result<(), StrixError> {
+        self.emit(OpCode::Loop);
+        let distance = self.chunk.code.len() + 2 - start;
+        let distance = u16::try_from(distance)
+            .map_err(|_| self.too_large(Msg::JumpTooFar, u16::MAX as usize))?;
+
+        self.emit_u16(distance);
+        Ok(())
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize) -> Result<(), StrixError> {
+        let distance = self.chunk.code.len() - offset - 2;
+        let distance = u16::try_from(distance)
+            .map_err(|_| self.too_large(Msg::JumpTooFar, u16::MAX as usize))?;
+
+        self.chunk.code[offset..offset + 2].copy_from_slice(&distance.to_be_bytes());
+        Ok(())
+    }
+}
+
+impl Visitor<'_, Result<(), StrixError>> for Compiler {
+    fn visit_binary_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(left)?;
+        self.compile_expr(right)?;
+
+        let op = match operator.token_type {
+            TokenType::Plus => OpCode::Add,
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Percent => OpCode::Modulo,
+            TokenType::Ampersand => OpCode::BitAnd,
+            TokenType::Pipe => OpCode::BitOr,
+            TokenType::Caret => OpCode::BitXor,
+            TokenType::LessLess => OpCode::ShiftLeft,
+            TokenType::GreaterGreater => OpCode::ShiftRight,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::BangEqual => OpCode::NotEqual,
+            _ => {
+                return Err(StrixError::at(
+                    ErrorCode::UnsupportedOperator,
+                    operator,
+                    tr(Msg::UnsupportedBinaryOperator, &[&operator.lexeme]),
+                ));
+            }
+        };
+        self.at(operator).emit(op);
+        Ok(())
+    }
+
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> Result<(), StrixError> {
+        self.compile_expr(expression)
+    }
+
+    fn visit_literal_expr(&mut self, value: &Literal) -> Result<(), StrixError> {
+        match value {
+            Literal::Nil => self.emit(OpCode::Nil),
+            Literal::Bool(true) => self.emit(OpCode::True),
+            Literal::Bool(false) => self.emit(OpCode::False),
+            Literal::Number(n) => self.emit_constant(ConstantKey::Number(n.to_bits()))?,
+            Literal::String(s) => {
+                let symbol = self.strings.intern(s);
+                self.emit_constant(ConstantKey::String(symbol))?
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<(), StrixError> {
+        self.compile_expr(right)?;
+
+        let op = match operator.token_type {
+            TokenType::Bang => OpCode::Not,
+            TokenType::Minus => OpCode::Negate,
+            _ => {
+                return Err(StrixError::at(
+                    ErrorCode::UnsupportedOperator,
+                    operator,
+                    tr(Msg::UnsupportedUnaryOperator, &[&operator.lexeme]),
+                ));
+            }
+        };
+        self.at(operator).emit(op);
+        Ok(())
+    }
+
+    fn visit_ternary_expr(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(condition)?;
+
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.compile_expr(then_branch)?;
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(else_jump)?;
+        self.emit(OpCode::Pop);
+        self.compile_expr(else_branch)?;
+        self.patch_jump(end_jump)
+    }
+
+    fn visit_coalesce_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(left)?;
+
+        let end_jump = self.at(operator).emit_jump(OpCode::JumpIfNotNil);
+        self.emit(OpCode::Pop);
+        self.compile_expr(right)?;
+        self.patch_jump(end_jump)
+    }
+
+    fn visit_range_expr(
+        &mut self,
+        start: &Expr,
+        operator: &Token,
+        end: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(start)?;
+        self.compile_expr(end)?;
+
+        let op = if operator.token_type == TokenType::DotDotEqual {
+            OpCode::RangeInclusive
+        } else {
+            OpCode::Range
+        };
+        self.at(operator).emit(op);
+        Ok(())
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token, elements: &[Expr]) -> Result<(), StrixError> {
+        for element in elements {
+            self.compile_expr(element)?;
+        }
+
+        self.at(bracket);
+        let count = self.count(elements.len(), Msg::TooManyElements)?;
+        self.emit(OpCode::List);
+        self.emit_u16(count);
+        Ok(())
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        brace: &Token,
+        entries: &[(Expr, Expr)],
+    ) -> Result<(), StrixError> {
+        for (key, value) in entries {
+            self.compile_expr(key)?;
+            self.compile_expr(value)?;
+        }
+
+        self.at(brace);
+        let count = self.count(entries.len(), Msg::TooManyElements)?;
+        self.emit(OpCode::Map);
+        self.emit_u16(count);
+        Ok(())
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(object)?;
+        self.compile_expr(index)?;
+
+        self.at(bracket).emit(OpCode::Index);
+        Ok(())
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(object)?;
+        for bound in [start, end] {
+            match bound {
+                Some(expr) => self.compile_expr(expr)?,
+                None => self.at(bracket).emit(OpCode::Nil),
+            }
+        }
+
+        self.at(bracket).emit(OpCode::Slice);
+        Ok(())
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        value: &Expr,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(object)?;
+        self.compile_expr(index)?;
+        self.compile_expr(value)?;
+
+        let Some(position) = ASSIGN_OPERATORS
+            .iter()
+            .position(|op| *op == operator.token_type)
+        else {
+            return Err(StrixError::at(
+                ErrorCode::UnsupportedOperator,
+                operator,
+                tr(Msg::UnsupportedAssignmentOperator, &[&operator.lexeme]),
+            ));
+        };
+
+        // Indexing errors are reported at the bracket and arithmetic errors at the operator,
+        // so the operand byte carries the operator's span.
+        self.at(bracket).emit(OpCode::SetIndex);
+        self.at(operator).emit_byte(position as u8);
+        Ok(())
+    }
+
+    fn visit_increment_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        prefix: bool,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(object)?;
+        self.compile_expr(index)?;
+
+        let mut flags = 0;
+        if operator.token_type == TokenType::MinusMinus {
+            flags |= DECREMENT;
+        }
+        if prefix {
+            flags |= PREFIX;
+        }
+
+        self.at(bracket).emit(OpCode::IncrementIndex);
+        self.at(operator).emit_byte(flags);
+        Ok(())
+    }
+
+    fn visit_invoke_expr(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+        arguments: &[Expr],
+        optional: bool,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(object)?;
+        // A nil object is left as the result of an optional call.
+        let nil_jump = if optional {
+            Some(self.at(name).emit_jump(OpCode::JumpIfNil))
+        } else {
+            None
+        };
+        for argument in arguments {
+            self.compile_expr(argument)?;
+        }
+
+        self.at(name);
+        let count = u8::try_from(arguments.len())
+            .map_err(|_| self.too_large(Msg::TooManyArguments, u8::MAX as usize))?;
+        let symbol = self.strings.intern(name.lexeme);
+        let constant = self.constant(ConstantKey::String(symbol))?;
+        self.emit(OpCode::Invoke);
+        self.emit_u16(constant);
+        self.emit_byte(count);
+        match nil_jump {
+            Some(jump) => self.patch_jump(jump),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_throw_expr(&mut self, keyword: &Token, value: &Expr) -> Result<(), StrixError> {
+        self.compile_expr(value)?;
+
+        self.at(keyword).emit(OpCode::Throw);
+
+        // Like a loop control, a throw counts as leaving a value for the code after it.
+        self.settle();
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token, slot: usize) -> Result<(), StrixError> {
+        self.at(name);
+        let slot = self.count(slot, Msg::TooManyLocals)?;
+        self.emit(OpCode::GetLocal);
+        self.emit_u16(slot);
+        Ok(())
+    }
+
+    fn visit_block_expr(&mut self, brace: &Token, expressions: &[Expr]) -> Result<(), StrixError> {
+        if expressions.is_empty() {
+            self.at(brace).emit(OpCode::Nil);
+        }
+
+        for (i, expression) in expressions.iter().enumerate() {
+            if i > 0 {
+                self.emit(OpCode::Pop);
+            }
+            self.compile_expr(expression)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles a loop that keeps its iterator in a local and collects the value of each pass
+    /// into a list on the stack:
+    ///
+    /// ```text
+    ///         <iterable> Iter PushLocal List 0
+    /// start:  ForNext iterator, exit
+    ///         PushLocal <body> PopLocal Append Loop start
+    /// exit:   PopLocal
+    /// ```
+    ///
+    /// A `continue` loops back to `start` and a `break` jumps to `exit`, each after dropping
+    /// what the pass has added to the stack and the locals.
+    fn visit_for_expr(
+        &mut self,
+        _label: Option<&Token>,
+        keyword: &Token,
+        _variable: &Token,
+        iterable: &Expr,
+        body: &Expr,
+        collect: bool,
+    ) -> Result<(), StrixError> {
+        self.compile_expr(iterable)?;
+
+        self.at(keyword);
+        let iterator = self.count(self.locals, Msg::TooManyLocals)?;
+        self.emit(OpCode::Iter);
+        self.emit(OpCode::PushLocal);
+        if collect {
+            self.emit(OpCode::List);
+            self.emit_u16(0);
+        }
+        self.locals += 1;
+
+        self.settle();
+        let start = self.chunk.code.len();
+        self.loops.push(Loop {
+            start,
+            depth: self.depth,
+            locals: self.locals,
+            breaks: vec![],
+        });
+        self.emit(OpCode::ForNext);
+        self.emit_u16(iterator);
+        let exit_jump = self.emit_jump_operand();
+        self.emit(OpCode::PushLocal);
+        self.locals += 1;
+
+        self.compile_expr(body)?;
+
+        self.at(keyword).emit(OpCode::PopLocal);
+        self.locals -= 1;
+        self.emit(if collect { OpCode::Append } else { OpCode::Pop });
+        self.emit_loop(start)?;
+
+        self.patch_jump(exit_jump)?;
+        for jump in self.loops.pop().map(|done| done.breaks).unwrap_or_default() {
+            self.patch_jump(jump)?;
+        }
+        self.emit(OpCode::PopLocal);
+        self.locals -= 1;
+        if !collect {
+            self.emit(OpCode::Nil);
+        }
+        Ok(())
+    }
+
+    fn visit_break_expr(
+        &mut self,
+        keyword: &Token,
+        _label: Option<&Token>,
+        loops: usize,
+    ) -> Result<(), StrixError> {
+        self.loop_control(keyword, loops)
+    }
+
+    fn visit_continue_expr(
+        &mut self,
+        keyword: &Token,
+        _label: Option<&Token>,
+        loops: usize,
+    ) -> Result<(), StrixError> {
+        self.loop_control(keyword, loops)
+    }
+
+    /// Compiles `try { body } catch (name) { handler } finally { cleanup }` as
+    ///
+    /// ```text
+    ///          Try catch
+    ///          <body>
+    ///          EndTry
+    ///          Jump done
+    /// catch:   Caught PushLocal
+    ///          Try rethrow
+    ///          <handler>
+    ///          EndTry PopLocal
+    ///          Jump done
+    /// rethrow: PopLocal
+    ///          Constant THREW
+    ///          Jump finally
+    /// done:    Constant COMPLETED
+    /// finally: <cleanup> Pop PushLocal
+    ///          GetLocal kind Constant THREW Equal JumpIfFalse next
+    ///          Pop Throw
+    /// next:    Pop
+    ///          ...
+    ///          PopLocal
+    /// ```
+    ///
+    /// The cleanup is compiled once, and runs with how the try ended on the stack above its
+    /// value. It then moves that into a local and dispatches on it: it throws the error
+    /// again, goes on with one of the `break`s and `continue`s that left through it, or
+    /// drops the local to leave the value. Each exit checks its own kind after `THREW`, like
+    /// the throw, and leaves from there as `leave` does.
+    ///
+    /// Without a catch clause, errors from the body go straight to `rethrow`, which has no
+    /// local to drop. Without a finally clause, errors from the handler are not caught, so
+    /// there is no `rethrow`, and the code ends at `done`.
+    fn visit_try_expr(
+        &mut self,
+        keyword: &Token,
+        body: &Expr,
+        handler: Option<(&Token, &Expr)>,
+        finally: Option<&Expr>,
+    ) -> Result<(), StrixError> {
+        self.at(keyword).settle();
+        let (depth, locals) = (self.depth, self.locals);
+        self.trys.push(Try {
+            depth,
+            locals,
+            loops: self.loops.len(),
+            handler: true,
+            finally: finally.is_some(),
+            exits: vec![],
+            exit_jumps: vec![],
+        });
+
+        let catch_jump = self.emit_jump(OpCode::Try);
+        self.compile_expr(body)?;
+        self.at(keyword).emit(OpCode::EndTry);
+        let mut done_jumps = vec![self.emit_jump(OpCode::Jump)];
+
+        // Handlers start with the error pushed.
+        self.patch_jump(catch_jump)?;
+        self.settle();
+        self.depth = depth + 1;
+
+        if let Some((_variable, handler)) = handler {
+            // The parser gave the caught value the next free slot.
+            self.emit(OpCode::Caught);
+            self.emit(OpCode::PushLocal);
+            self.locals += 1;
+
+            let rethrow_jump = finally.is_some().then(|| self.emit_jump(OpCode::Try));
+            if let Some(try_) = self.trys.last_mut() {
+                try_.handler = rethrow_jump.is_some();
+            }
+            self.compile_expr(handler)?;
+
+            self.at(keyword);
+            if rethrow_jump.is_some() {
+                self.emit(OpCode::EndTry);
+            }
+            self.emit(OpCode::PopLocal);
+            self.locals -= 1;
+
+            if let Some(rethrow_jump) = rethrow_jump {
+                done_jumps.push(self.emit_jump(OpCode::Jump));
+                self.patch_jump(rethrow_jump)?;
+                self.settle();
+                self.depth = depth + 1;
+                // The caught value is still in scope here, which `locals` no longer counts.
+                self.emit(OpCode::PopLocal);
+            }
+        }
+        let Some(try_) = self.trys.pop() else {
+            unreachable!("the try was pushed above");
+        };
+
+        let Some(finally) = finally else {
+            for jump in done_jumps {
+                self.patch_jump(jump)?;
+            }
+            return Ok(());
+        };
+
+        self.emit_kind(THREW)?;
+        let mut finally_jumps = vec![self.emit_jump(OpCode::Jump)];
+        for jump in done_jumps {
+            self.patch_jump(jump)?;
+        }
+        self.settle();
+        self.depth = depth + 1;
+        self.emit_kind(COMPLETED)?;
+
+        finally_jumps.extend(try_.exit_jumps);
+        for jump in finally_jumps {
+            self.patch_jump(jump)?;
+        }
+        self.compile_expr(finally)?;
+        self.at(keyword).emit(OpCode::Pop);
+        self.emit(OpCode::PushLocal);
+        self.locals += 1;
+        let slot = self.count(locals, Msg::TooManyLocals)?;
+
+        let kinds = std::iter::once(None).chain(try_.exits.into_iter().map(Some));
+        for (kind, exit) in (THREW..).zip(kinds) {
+            self.emit(OpCode::GetLocal);
+            self.emit_u16(slot);
+            self.emit_kind(kind)?;
+            self.emit(OpCode::Equal);
+            let next_jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit(OpCode::Pop);
+            match exit {
+                None => self.emit(OpCode::Throw),
+                Some((control, index)) => self.leave(control, index)?,
+            }
+
+            self.patch_jump(next_jump)?;
+            self.settle();
+            self.depth = depth + 2;
+            self.locals = locals + 1;
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::PopLocal);
+        self.locals = locals;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use crate::{
+    chunk::{ASSIGN_OPERATORS, Chunk, DECREMENT, OpCode, PREFIX},
+    value::Value,
+};
+
+/// Lists every instruction of a chunk compiled from the function `name`.
+pub fn disassemble(chunk: &Chunk, name: &str) -> String {
+    let mut out = format!("== {name} ==\n");
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (text, next) = instruction(chunk, offset);
+        out.push_str(&text);
+        out.push('\n');
+        offset = next;
+    }
+
+    out
+}
+
+/// Describes the instruction at `offset` as its offset, source line, opcode and operands,
+/// returning the offset of the next instruction.
+pub fn instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let mut out = format!("{offset:04} ");
+
+    let line = chunk.span_at(offset).line;
+    if offset > 0 && chunk.span_at(offset - 1).line == line {
+        out.push_str("   | ");
+    } else {
+        let _ = write!(out, "{line:4} ");
+    }
+
+    let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+        let _ = write!(out, "<unknown opcode {}>", chunk.code[offset]);
+        return (out, offset + 1);
+    };
+    let next = offset + 1 + op.operand_len();
+    let Some(operands) = chunk.code.get(offset + 1..next) else {
+        let _ = write!(out, "{op:?} <truncated>");
+        return (out, chunk.code.len());
+    };
+    let u16_operand = || u16::from_be_bytes([operands[0], operands[1]]) as usize;
+
+    let _ = write!(out, "{:<16}", format!("{op:?}"));
+    let _ = match op {
+        OpCode::Constant => {
+            let index = u16_operand();
+            write!(out, "{index:4} {}", constant(chunk, index))
+        }
+        OpCode::Jump
+        | OpCode::JumpIfFalse
+        | OpCode::JumpIfNotNil
+        | OpCode::JumpIfNil
+        | OpCode::Try => {
+            let distance = u16_operand();
+            write!(out, "{distance:4} -> {:04}", next + distance)
+        }
+        OpCode::Loop => {
+            let distance = u16_operand();
+            match next.checked_sub(distance) {
+                Some(target) => write!(out, "{distance:4} -> {target:04}"),
+                None => write!(out, "{distance:4} -> <before start>"),
+            }
+        }
+        OpCode::ForNext => {
+            let distance = u16::from_be_bytes([operands[2], operands[3]]) as usize;
+            write!(out, "{:4} -> {:04}", u16_operand(), next + distance)
+        }
+        OpCode::List | OpCode::Map | OpCode::GetLocal => write!(out, "{:4}", u16_operand()),
+        OpCode::SetIndex => match ASSIGN_OPERATORS.get(operands[0] as usize) {
+            Some(operator) => write!(out, "{:4} {operator}", operands[0]),
+            None => write!(out, "{:4} <unknown operator>", operands[0]),
+        },
+        OpCode::IncrementIndex => {
+            let fix = if operands[0] & PREFIX != 0 {
+                "prefix"
+            } else {
+                "postfix"
+            };
+            let op = if operands[0] & DECREMENT != 0 {
+                "--"
+            } else {
+                "++"
+            };
+            write!(out, "{:4} {fix} {op}", operands[0])
+        }
+        OpCode::Invoke => {
+            let index = u16_operand();
+            write!(
+                out,
+                "{index:4} {} ({} args)",
+                constant(chunk, index),
+                operands[2]
+            )
+        }
+        _ => Ok(()),
+    };
+
+    (out.trim_end().to_string(), next)
+}
+
+/// Shows a value the way it is written in source, so strings are quoted.
+pub fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{s:?}"),
+        value => value.to_string(),
+    }
+}
+
+fn constant(chunk: &Chunk, index: usize) -> String {
+    match chunk.constants.get(index) {
+        Some(value) => show(value),
+        None => "<missing constant>".to_string(),
+    }
+}
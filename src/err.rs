@@ -1,27 +1,512 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, ops::Deref};
 
-use crate::tokenizer::{Token, TokenType};
+use crate::{
+    messages::{self, Locale, Msg, tr_in},
+    tokenizer::Token,
+};
 
-#[derive(Debug)]
-pub enum StrixError {
-    ParserError(Token, String),
-    ScannerError(usize, String),
-    InterpreterError(Token, String),
+/// The broad category of an error, for hosts that need to react to errors programmatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Lexical,
+    Syntax,
+    Resolve,
+    Type,
+    Runtime,
+    Io,
+    Limit,
 }
 
-impl fmt::Display for StrixError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Lexical => "Lexical",
+            Self::Syntax => "Syntax",
+            Self::Resolve => "Resolve",
+            Self::Type => "Type",
+            Self::Runtime => "Runtime",
+            Self::Io => "IO",
+            Self::Limit => "Limit",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A stable identifier for each distinct error. Codes never change meaning once released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    // Lexical errors.
+    UnexpectedCharacter,
+    UnterminatedString,
+
+    // Syntax errors.
+    ExpectedExpression,
+    ExpectedToken,
+    InvalidAssignmentTarget,
+    InvalidIncrementTarget,
+
+    // Resolve errors.
+    LoopControlOutsideLoop,
+    UndefinedVariable,
+    UndefinedLabel,
+
+    // Type errors.
+    OperandsMustBeNumbers,
+    InvalidAddOperands,
+    OperandMustBeNumber,
+    OperandsMustBeIntegers,
+    NotIndexable,
+    NotSliceable,
+    InvalidIndex,
+    UnhashableKey,
+    InvalidRangeBounds,
+    UndefinedMethod,
+    WrongArity,
+    NotIterable,
+
+    // Runtime errors.
+    IndexOutOfBounds,
+    EmptyList,
+    UnsupportedOperator,
+    UncaughtThrow,
+    IteratorExhausted,
+    RemainderByZero,
+
+    // Limit errors.
+    NestingTooDeep,
+    StackOverflow,
+    ChunkTooLarge,
+    OutOfMemory,
+
+    // IO errors.
+    UnreadableFile,
+    InvalidEncoding,
+    UnwritableFile,
+    NotBytecode,
+    BytecodeVersion,
+    CorruptBytecode,
+    InvalidBytecode,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 38] = [
+        Self::UnexpectedCharacter,
+        Self::UnterminatedString,
+        Self::ExpectedExpression,
+        Self::ExpectedToken,
+        Self::InvalidAssignmentTarget,
+        Self::InvalidIncrementTarget,
+        Self::LoopControlOutsideLoop,
+        Self::UndefinedVariable,
+        Self::UndefinedLabel,
+        Self::OperandsMustBeNumbers,
+        Self::InvalidAddOperands,
+        Self::OperandMustBeNumber,
+        Self::OperandsMustBeIntegers,
+        Self::NotIndexable,
+        Self::NotSliceable,
+        Self::InvalidIndex,
+        Self::UnhashableKey,
+        Self::InvalidRangeBounds,
+        Self::UndefinedMethod,
+        Self::WrongArity,
+        Self::NotIterable,
+        Self::IndexOutOfBounds,
+        Self::EmptyList,
+        Self::UnsupportedOperator,
+        Self::UncaughtThrow,
+        Self::IteratorExhausted,
+        Self::RemainderByZero,
+        Self::NestingTooDeep,
+        Self::StackOverflow,
+        Self::ChunkTooLarge,
+        Self::OutOfMemory,
+        Self::UnreadableFile,
+        Self::InvalidEncoding,
+        Self::UnwritableFile,
+        Self::NotBytecode,
+        Self::BytecodeVersion,
+        Self::CorruptBytecode,
+        Self::InvalidBytecode,
+    ];
+
+    /// Looks up a code by its identifier, such as `E0301`. Lowercase identifiers are accepted.
+    pub fn parse(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnexpectedCharacter => "E0001",
+            Self::UnterminatedString => "E0002",
+            Self::ExpectedExpression => "E0100",
+            Self::ExpectedToken => "E0101",
+            Self::InvalidAssignmentTarget => "E0102",
+            Self::InvalidIncrementTarget => "E0103",
+            Self::LoopControlOutsideLoop => "E0200",
+            Self::UndefinedVariable => "E0201",
+            Self::UndefinedLabel => "E0202",
+            Self::OperandsMustBeNumbers => "E0300",
+            Self::InvalidAddOperands => "E0301",
+            Self::OperandMustBeNumber => "E0302",
+            Self::OperandsMustBeIntegers => "E0303",
+            Self::NotIndexable => "E0304",
+            Self::NotSliceable => "E0305",
+            Self::InvalidIndex => "E0306",
+            Self::UnhashableKey => "E0307",
+            Self::InvalidRangeBounds => "E0308",
+            Self::UndefinedMethod => "E0309",
+            Self::WrongArity => "E0310",
+            Self::NotIterable => "E0311",
+            Self::IndexOutOfBounds => "E0400",
+            Self::EmptyList => "E0401",
+            Self::UnsupportedOperator => "E0402",
+            Self::UncaughtThrow => "E0403",
+            Self::IteratorExhausted => "E0404",
+            Self::RemainderByZero => "E0405",
+            Self::NestingTooDeep => "E0500",
+            Self::StackOverflow => "E0501",
+            Self::ChunkTooLarge => "E0502",
+            Self::OutOfMemory => "E0503",
+            Self::UnreadableFile => "E0600",
+            Self::InvalidEncoding => "E0601",
+            Self::UnwritableFile => "E0602",
+            Self::NotBytecode => "E0603",
+            Self::BytecodeVersion => "E0604",
+            Self::CorruptBytecode => "E0605",
+            Self::InvalidBytecode => "E0606",
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
         match self {
-            Self::ParserError(t, m) | Self::InterpreterError(t, m) => {
-                if t.token_type == TokenType::Eof {
-                    write!(f, "[line {}] Error at the end: {}", t.line, m)
-                } else {
-                    write!(f, "[line {}] Error at '{}': {}", t.line, t.lexeme, m)
-                }
+            Self::UnexpectedCharacter | Self::UnterminatedString => ErrorKind::Lexical,
+            Self::ExpectedExpression
+            | Self::ExpectedToken
+            | Self::InvalidAssignmentTarget
+            | Self::InvalidIncrementTarget => ErrorKind::Syntax,
+            Self::LoopControlOutsideLoop | Self::UndefinedVariable | Self::UndefinedLabel => {
+                ErrorKind::Resolve
             }
-            Self::ScannerError(l, m) => write!(f, "[line {l}] Error: {m}"),
+            Self::OperandsMustBeNumbers
+            | Self::InvalidAddOperands
+            | Self::OperandMustBeNumber
+            | Self::OperandsMustBeIntegers
+            | Self::NotIndexable
+            | Self::NotSliceable
+            | Self::InvalidIndex
+            | Self::UnhashableKey
+            | Self::InvalidRangeBounds
+            | Self::UndefinedMethod
+            | Self::WrongArity
+            | Self::NotIterable => ErrorKind::Type,
+            Self::IndexOutOfBounds
+            | Self::EmptyList
+            | Self::UnsupportedOperator
+            | Self::UncaughtThrow
+            | Self::IteratorExhausted
+            | Self::RemainderByZero => ErrorKind::Runtime,
+            Self::NestingTooDeep
+            | Self::StackOverflow
+            | Self::ChunkTooLarge
+            | Self::OutOfMemory => ErrorKind::Limit,
+            Self::UnreadableFile
+            | Self::InvalidEncoding
+            | Self::UnwritableFile
+            | Self::NotBytecode
+            | Self::BytecodeVersion
+            | Self::CorruptBytecode
+            | Self::InvalidBytecode => ErrorKind::Io,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A region of source code. Offsets are in bytes, lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+}
+
+/// A secondary span with a message explaining its part in an error.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Identifies the source file an error came from. Hosts map ids back to file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileId(pub usize);
+
+/// An error from any stage of running a script. The details live behind a box so that
+/// results stay small, as they are returned through every level of the recursive parser
+/// and interpreter. Fields are read through `Deref`, as in `err.kind`.
+#[derive(Debug, Clone)]
+pub struct StrixError(Box<ErrorDetails>);
+
+#[derive(Debug, Clone)]
+pub struct ErrorDetails {
+    pub kind: ErrorKind,
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub file: Option<FileId>,
+    pub stack: Vec<StackFrame>,
+    /// The value of an uncaught `throw`, as it displays. The value itself stays with the
+    /// engine that threw it, so that errors can be sent across threads.
+    pub value: Option<String>,
+    /// The language its messages were created in, which rendering keeps to.
+    pub locale: Locale,
+}
+
+impl StrixError {
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        Self(Box::new(ErrorDetails {
+            kind: code.kind(),
+            code,
+            message,
+            span: None,
+            labels: vec![],
+            notes: vec![],
+            file: None,
+            stack: vec![],
+            value: None,
+            locale: messages::locale(),
+        }))
+    }
+
+    /// Creates an error located at a token.
+    pub fn at(code: ErrorCode, token: &Token, message: String) -> Self {
+        Self::new(code, message).with_span(token.span)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.0.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.0.labels.push(Label { span, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.0.notes.push(note);
+        self
+    }
+
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.0.file.get_or_insert(file);
+        self
+    }
+
+    pub fn with_stack(mut self, stack: Vec<StackFrame>) -> Self {
+        self.0.stack = stack;
+        self
+    }
+
+    pub fn with_value(mut self, value: String) -> Self {
+        self.0.value = Some(value);
+        self
+    }
+
+    /// Whether a `catch` clause can handle the error. Type and runtime errors can, and so can
+    /// a stack overflow, which is raised before the call that would exceed the limit starts.
+    /// Exceeding any other limit stops the script regardless.
+    pub fn is_catchable(&self) -> bool {
+        matches!(self.kind, ErrorKind::Type | ErrorKind::Runtime)
+            || self.code == ErrorCode::StackOverflow
+    }
+
+    /// Formats the error, showing at most `max_frames` frames of its stack trace.
+    pub fn render(&self, max_frames: usize) -> String {
+        let locale = self.locale;
+        let header = tr_in(
+            locale,
+            Msg::KindError,
+            &[
+                &messages::kind_name(locale, self.kind),
+                &self.code,
+                &self.message,
+            ],
+        );
+        let mut buffer = match self.span {
+            Some(span) => format!("{} {header}", location(locale, span)),
+            None => header,
+        };
+
+        for label in &self.labels {
+            let location = location(locale, label.span);
+            buffer.push_str(&format!("\n    {location} {}", label.message));
+        }
+        for note in &self.notes {
+            buffer.push_str(&format!("\n    {}", tr_in(locale, Msg::Help, &[note])));
         }
+        for frame in self.stack.iter().take(max_frames) {
+            buffer.push_str(&format!("\n    {}", frame.render(locale)));
+        }
+        if self.stack.len() > max_frames {
+            let more = self.stack.len() - max_frames;
+            buffer.push_str(&format!(
+                "\n    {}",
+                tr_in(locale, Msg::MoreFrames, &[&more])
+            ));
+        }
+
+        buffer
+    }
+
+    /// Formats the error as a single-line JSON object for tools. `file` is the name the
+    /// host registered for the error's `FileId`.
+    pub fn render_json(&self, file: Option<&str>) -> String {
+        let span = match self.span {
+            Some(span) => format!(
+                "\"line\":{},\"column\":{},\"span\":{}",
+                span.line,
+                span.column,
+                json_span(span)
+            ),
+            None => "\"line\":null,\"column\":null,\"span\":null".to_string(),
+        };
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{{\"span\":{},\"message\":{}}}",
+                    json_span(label.span),
+                    json_string(&label.message)
+                )
+            })
+            .collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        let stack: Vec<String> = self
+            .stack
+            .iter()
+            .map(|frame| {
+                format!(
+                    "{{\"function\":{},\"file\":{},\"line\":{}}}",
+                    json_string(&frame.function),
+                    json_string(&frame.file),
+                    frame.line
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"kind\":{},\"code\":{},\"message\":{},\"file\":{},{},\
+             \"labels\":[{}],\"notes\":[{}],\"stack\":[{}]}}",
+            json_string(&self.kind.to_string().to_lowercase()),
+            json_string(self.code.as_str()),
+            json_string(&self.message),
+            file.map_or("null".to_string(), json_string),
+            span,
+            labels.join(","),
+            notes.join(","),
+            stack.join(",")
+        )
+    }
+}
+
+fn location(locale: Locale, span: Span) -> String {
+    tr_in(locale, Msg::Location, &[&span.line, &span.column])
+}
+
+fn json_span(span: Span) -> String {
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+        span.start, span.end, span.line, span.column
+    )
+}
+
+/// Quotes and escapes a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut buffer = String::with_capacity(s.len() + 2);
+    buffer.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buffer.push_str("\\\""),
+            '\\' => buffer.push_str("\\\\"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            '\t' => buffer.push_str("\\t"),
+            c if c.is_control() => buffer.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buffer.push(c),
+        }
+    }
+    buffer.push('"');
+    buffer
+}
+
+impl Deref for StrixError {
+    type Target = ErrorDetails;
+
+    fn deref(&self) -> &ErrorDetails {
+        &self.0
+    }
+}
+
+impl fmt::Display for StrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(usize::MAX))
     }
 }
 
 impl Error for StrixError {}
+
+/// A call that was active when a runtime error happened, as shown in a stack trace.
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub function: String,
+    pub file: String,
+    pub line: usize,
+}
+
+impl StackFrame {
+    pub fn new(function: String, file: String, line: usize) -> Self {
+        Self {
+            function,
+            file,
+            line,
+        }
+    }
+
+    /// Formats the frame in `locale`.
+    pub fn render(&self, locale: Locale) -> String {
+        tr_in(
+            locale,
+            Msg::Frame,
+            &[&self.function, &self.file, &self.line],
+        )
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(messages::locale()))
+    }
+}
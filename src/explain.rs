@@ -0,0 +1,292 @@
+use std::fmt;
+
+use crate::err::ErrorCode;
+
+/// The long-form description of an error code, as printed by `strix --explain`.
+pub struct Explanation {
+    pub code: ErrorCode,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// A script that fails with the code, and a corrected version of it.
+    pub example: Option<(&'static str, &'static str)>,
+}
+
+impl Explanation {
+    pub fn of(code: ErrorCode) -> Self {
+        let (title, description, example) = match code {
+            ErrorCode::UnexpectedCharacter => (
+                "Unexpected character",
+                "The scanner found a character that does not start any token. Strix has no '@', \
+                 '#', '$' or backtick tokens.",
+                Some(("1 @ 2", "1 + 2")),
+            ),
+            ErrorCode::UnterminatedString => (
+                "Unterminated string",
+                "A string literal was opened with '\"' but the source ended before the closing \
+                 quote. Strings may span several lines, so the missing quote can be far from \
+                 where the error is reported.",
+                Some(("\"hello", "\"hello\"")),
+            ),
+            ErrorCode::ExpectedExpression => (
+                "Expected an expression",
+                "The parser needed a value, such as a literal, a list or a parenthesized \
+                 expression, but found something else. This usually means an operator is missing \
+                 its right operand or an index is empty.",
+                Some(("[1, 2][]", "[1, 2][0]")),
+            ),
+            ErrorCode::ExpectedToken => (
+                "Expected a specific token",
+                "The parser needed a particular token, usually a closing delimiter, a ':' after a \
+                 map key or a method name after '.'. When a delimiter is missing, the error also \
+                 points at the one it should close.",
+                Some(("{\"a\" 1}", "{\"a\": 1}")),
+            ),
+            ErrorCode::InvalidAssignmentTarget => (
+                "Invalid assignment target",
+                "The left side of '=' or of a compound assignment such as '+=' must be a place \
+                 that can hold a value. Only indexed list elements and map entries are places; \
+                 literals, slices and other expressions are not.",
+                Some(("1 += 2", "[1][0] += 2")),
+            ),
+            ErrorCode::InvalidIncrementTarget => (
+                "Invalid increment target",
+                "'++' and '--' change the value stored in a place, so their operand must be an \
+                 indexed list element or map entry.",
+                Some(("(1)++", "[1][0]++")),
+            ),
+            ErrorCode::LoopControlOutsideLoop => (
+                "'break' or 'continue' outside of a loop",
+                "'break' leaves a loop early and 'continue' skips the rest of its current pass, \
+                 so both are only allowed inside the body of a 'for' loop.",
+                Some(("break", "for x in [1, 2] { break }")),
+            ),
+            ErrorCode::UndefinedVariable => (
+                "Undefined variable",
+                "The name does not refer to anything. The only variables are loop variables, \
+                 which exist inside the body of their 'for' loop, so this is usually a misspelled \
+                 keyword such as 'ture' or a loop variable used after its loop. The error \
+                 suggests the closest keyword or variable when there is one.",
+                Some(("ture", "true")),
+            ),
+            ErrorCode::UndefinedLabel => (
+                "Undefined loop label",
+                "'break' and 'continue' can name the loop they apply to by its label, as in \
+                 \"'outer: for x in xs { break 'outer }\". The label must belong to a loop \
+                 around them; the error suggests the closest one when there is one.",
+                Some((
+                    "for x in [1] { break 'outer }",
+                    "'outer: for x in [1] { break 'outer }",
+                )),
+            ),
+            ErrorCode::OperandsMustBeNumbers => (
+                "Operands must be numbers",
+                "Arithmetic and comparison operators other than '+' and '==' only work on \
+                 numbers. Values are never converted implicitly.",
+                Some(("\"3\" * 2", "3 * 2")),
+            ),
+            ErrorCode::InvalidAddOperands => (
+                "Invalid operands to '+'",
+                "'+' adds two numbers or joins two strings. Mixing a number with a string, or \
+                 using any other type, is an error rather than an implicit conversion.",
+                Some(("1 + \"a\"", "\"1\" + \"a\"")),
+            ),
+            ErrorCode::OperandMustBeNumber => (
+                "Operand must be a number",
+                "Unary '-' negates a number. Use '!' to negate a boolean.",
+                Some(("-true", "!true")),
+            ),
+            ErrorCode::OperandsMustBeIntegers => (
+                "Operands must be integers",
+                "Bitwise compound assignments such as '&=' and '<<=' work on whole numbers only, \
+                 and shift amounts must be between 0 and 63.",
+                Some(("[1.5][0] &= 1", "[3][0] &= 1")),
+            ),
+            ErrorCode::NotIndexable => (
+                "Value cannot be indexed",
+                "Only lists and maps support indexing with '[...]'.",
+                Some(("1[0]", "[1][0]")),
+            ),
+            ErrorCode::NotSliceable => (
+                "Value cannot be sliced",
+                "A slice such as 'xs[1:3]' copies part of a list. Other values, including maps, \
+                 cannot be sliced.",
+                Some(("{\"a\": 1}[0:1]", "[1, 2, 3][0:1]")),
+            ),
+            ErrorCode::InvalidIndex => (
+                "Invalid list index",
+                "List indices and slice bounds must be whole numbers. Negative values count from \
+                 the end of the list.",
+                Some(("[1, 2][0.5]", "[1, 2][-1]")),
+            ),
+            ErrorCode::UnhashableKey => (
+                "Unhashable map key",
+                "Map keys must be strings, numbers, bools or nil. Lists, maps and ranges cannot \
+                 be keys.",
+                Some(("{[1]: \"one\"}", "{1: \"one\"}")),
+            ),
+            ErrorCode::InvalidRangeBounds => (
+                "Invalid range bounds",
+                "Both ends of a range such as '0..n' must be whole numbers.",
+                Some(("0..2.5", "0..3")),
+            ),
+            ErrorCode::UndefinedMethod => (
+                "Undefined method",
+                "The value has no method with this name. Lists have push, pop, len, insert, \
+                 remove, contains and iter. Maps have keys, values, len, has, remove and iter. \
+                 Ranges have len, contains and iter, strings have iter, iterators have next, \
+                 done and iter, and caught errors have message, kind, code, line and column. \
+                 Other values have no methods.",
+                Some(("[3, 1].sort()", "[3, 1].len()")),
+            ),
+            ErrorCode::WrongArity => (
+                "Wrong number of arguments",
+                "A method was called with more or fewer arguments than it takes.",
+                Some(("[1].push()", "[1].push(2)")),
+            ),
+            ErrorCode::NotIterable => (
+                "Value cannot be iterated",
+                "A 'for' loop and 'iter()' walk over the elements of a list, the keys of a map, \
+                 the characters of a string, the integers of a range or the remaining values of \
+                 an iterator. Other values cannot be iterated.",
+                Some(("for x in 5 { x }", "for x in 0..5 { x }")),
+            ),
+            ErrorCode::IndexOutOfBounds => (
+                "List index out of bounds",
+                "The index is past the end of the list, or before its start when negative. Check \
+                 'len()' first, or use a slice, which clamps its bounds.",
+                Some(("[1, 2][2]", "[1, 2][1]")),
+            ),
+            ErrorCode::EmptyList => (
+                "Pop from an empty list",
+                "'pop' removes the last element, so the list must not be empty.",
+                Some(("[].pop()", "[1].pop()")),
+            ),
+            ErrorCode::UnsupportedOperator => (
+                "Unsupported operator",
+                "The interpreter met an operator it does not implement. The parser never produces \
+                 such an expression, so this indicates a bug in Strix itself.",
+                None,
+            ),
+            ErrorCode::UncaughtThrow => (
+                "Uncaught error",
+                "A 'throw' expression raised a value and no 'try' expression caught it, so the \
+                 script stopped. 'throw' is commonly the fallback of '??', failing loudly when a \
+                 value is missing. The error carries the thrown value, which is what a \
+                 'catch (e)' clause receives.",
+                Some((
+                    "{\"a\": 1}[\"b\"] ?? throw \"missing b\"",
+                    "{\"a\": 1}[\"b\"] ?? 0",
+                )),
+            ),
+            ErrorCode::IteratorExhausted => (
+                "Iterator exhausted",
+                "'next()' was called on an iterator that had already produced all of its \
+                 values. Check 'done()' first, or let a 'for' loop drive the iterator, which \
+                 stops at the end on its own.",
+                Some(("[].iter().next()", "[1].iter().next()")),
+            ),
+            ErrorCode::RemainderByZero => (
+                "Remainder by zero",
+                "The right operand of '%' or '%=' was zero. Division follows floating-point rules \
+                 and gives an infinity, but a remainder by zero has no meaningful value, so it is \
+                 an error rather than NaN. Check the divisor first.",
+                Some(("7 % 0", "7 % 2")),
+            ),
+            ErrorCode::NestingTooDeep => (
+                "Expression nested too deeply",
+                "Expressions are parsed recursively, so deeply nested parentheses, lists or long \
+                 operator chains could exhaust the native stack. The parser stops at a limit \
+                 instead, which can be raised with '--max-nesting' when the host has stack to \
+                 spare.",
+                None,
+            ),
+            ErrorCode::StackOverflow => (
+                "Stack overflow",
+                "More calls were active at once than the call-depth limit allows. The limit can \
+                 be raised with '--max-call-depth' when the host has stack to spare, and the \
+                 error can be caught with 'try'. Scripts cannot define functions yet, so at most \
+                 the script and one built-in method are active at once, and only a limit below 2 \
+                 is ever reached.",
+                None,
+            ),
+            ErrorCode::ChunkTooLarge => (
+                "Script too large to compile",
+                "The bytecode engine stores constant indexes, jump offsets and element counts in \
+                 16 bits, and argument counts in 8 bits. A script exceeded one of those limits. \
+                 Split large literals or branches into smaller ones, or run the script with \
+                 '--engine=tree'.",
+                None,
+            ),
+            ErrorCode::OutOfMemory => (
+                "Out of memory",
+                "Lists and maps that are still reachable took more memory than the heap limit \
+                 allows, even after collecting garbage. Sizes are estimates of the memory each \
+                 list or map uses. The limit can be raised with '--max-heap', and is unlimited \
+                 by default.",
+                None,
+            ),
+            ErrorCode::UnreadableFile => (
+                "Unable to read file",
+                "The script could not be read. Check that the path exists and is readable.",
+                None,
+            ),
+            ErrorCode::InvalidEncoding => (
+                "Invalid UTF-8",
+                "Scripts must be encoded as UTF-8. The error reports the byte offset of the first \
+                 invalid sequence. Re-save the file as UTF-8.",
+                None,
+            ),
+            ErrorCode::UnwritableFile => (
+                "Unable to write file",
+                "The compiled script could not be written. Check that the directory exists and \
+                 is writable.",
+                None,
+            ),
+            ErrorCode::NotBytecode => (
+                "Not a compiled script",
+                "Files ending in '.strixc' are loaded as bytecode, and this one does not start \
+                 with the bytecode header. Compile the script with 'strix compile', or run its \
+                 source instead.",
+                None,
+            ),
+            ErrorCode::BytecodeVersion => (
+                "Bytecode version mismatch",
+                "The script was compiled by a Strix whose bytecode format differs from this \
+                 one. Recompile it from source with this version of 'strix compile'.",
+                None,
+            ),
+            ErrorCode::CorruptBytecode => (
+                "Corrupt bytecode",
+                "The compiled script is truncated, or its contents no longer match the checksum \
+                 written when it was compiled. Recompile it from source.",
+                None,
+            ),
+            ErrorCode::InvalidBytecode => (
+                "Invalid bytecode",
+                "Compiled scripts are verified before they run, so that a malformed file cannot \
+                 crash the VM. This one has an unknown opcode, an operand out of range, a jump \
+                 outside the code, a stack that underflows or differs between branches, or code \
+                 that runs past its end. Files written by 'strix compile' always pass; recompile \
+                 the script from source.",
+                None,
+            ),
+        };
+
+        Self {
+            code,
+            title,
+            description,
+            example,
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}\n\n{}", self.code, self.title, self.description)?;
+        if let Some((wrong, right)) = self.example {
+            write!(f, "\n\nWrong:\n\n    {wrong}\n\nRight:\n\n    {right}")?;
+        }
+        Ok(())
+    }
+}
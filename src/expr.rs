@@ -3,43 +3,293 @@ use crate::{
     visitor::Visitor,
 };
 
-pub enum Expr {
+pub enum Expr<'a> {
     Binary {
-        left: Box<Expr>,
-        operator: Token,
-        right: Box<Expr>,
+        left: Box<Expr<'a>>,
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
     },
     Grouping {
-        expression: Box<Expr>,
+        expression: Box<Expr<'a>>,
     },
     Literal {
-        value: Literal,
+        value: Literal<'a>,
     },
     Unary {
-        operator: Token,
-        right: Box<Expr>,
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
+    },
+    Ternary {
+        condition: Box<Expr<'a>>,
+        then_branch: Box<Expr<'a>>,
+        else_branch: Box<Expr<'a>>,
+    },
+    Coalesce {
+        left: Box<Expr<'a>>,
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
+    },
+    Range {
+        start: Box<Expr<'a>>,
+        operator: Token<'a>,
+        end: Box<Expr<'a>>,
+    },
+    List {
+        bracket: Token<'a>,
+        elements: Vec<Expr<'a>>,
+    },
+    Map {
+        brace: Token<'a>,
+        entries: Vec<(Expr<'a>, Expr<'a>)>,
+    },
+    Index {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+    },
+    Slice {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        start: Option<Box<Expr<'a>>>,
+        end: Option<Box<Expr<'a>>>,
+    },
+    SetIndex {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+        operator: Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    IncrementIndex {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+        operator: Token<'a>,
+        prefix: bool,
+    },
+    /// Calls a method. An `optional` call, written `?.`, evaluates to nil without evaluating
+    /// its arguments when the object is nil. Only that call is skipped: later calls in the
+    /// chain need their own `?.`.
+    Invoke {
+        object: Box<Expr<'a>>,
+        name: Token<'a>,
+        arguments: Vec<Expr<'a>>,
+        optional: bool,
+    },
+    Throw {
+        keyword: Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    /// A loop variable, read from its slot among the variables in scope.
+    Variable {
+        name: Token<'a>,
+        slot: usize,
+    },
+    Block {
+        brace: Token<'a>,
+        expressions: Vec<Expr<'a>>,
+    },
+    /// Evaluates `body` for each value of `iterable`, collecting the results into a list when
+    /// `collect` is set. The parser clears it where the value of the loop is unused, so that
+    /// a loop run for its effects evaluates to nil and takes no memory per pass.
+    For {
+        label: Option<Token<'a>>,
+        keyword: Token<'a>,
+        variable: Token<'a>,
+        iterable: Box<Expr<'a>>,
+        body: Box<Expr<'a>>,
+        collect: bool,
+    },
+    /// Leaves a loop, `loops` loops out from the innermost one around it.
+    Break {
+        keyword: Token<'a>,
+        label: Option<Token<'a>>,
+        loops: usize,
+    },
+    /// Skips to the next pass of a loop, `loops` loops out from the innermost one around it.
+    Continue {
+        keyword: Token<'a>,
+        label: Option<Token<'a>>,
+        loops: usize,
+    },
+    /// Evaluates `body`, handing an error it raises to the `catch` variable and handler, if
+    /// any, and then evaluates `finally`, if any, however the rest ended, unless it ended with
+    /// an error that cannot be caught.
+    Try {
+        keyword: Token<'a>,
+        body: Box<Expr<'a>>,
+        handler: Option<(Token<'a>, Box<Expr<'a>>)>,
+        finally: Option<Box<Expr<'a>>>,
     },
 }
 
-impl Expr {
-    pub fn new_binary(left: Box<Expr>, operator: Token, right: Box<Expr>) -> Self {
+impl<'a> Expr<'a> {
+    pub fn new_binary(left: Box<Expr<'a>>, operator: Token<'a>, right: Box<Expr<'a>>) -> Self {
         Self::Binary {
             left,
             operator,
             right,
         }
     }
-    pub fn new_grouping(expression: Box<Expr>) -> Self {
+    pub fn new_grouping(expression: Box<Expr<'a>>) -> Self {
         Self::Grouping { expression }
     }
-    pub fn new_literal(value: Literal) -> Self {
+    pub fn new_literal(value: Literal<'a>) -> Self {
         Self::Literal { value }
     }
-    pub fn new_unary(operator: Token, right: Box<Expr>) -> Self {
+    pub fn new_unary(operator: Token<'a>, right: Box<Expr<'a>>) -> Self {
         Self::Unary { operator, right }
     }
+    pub fn new_ternary(
+        condition: Box<Expr<'a>>,
+        then_branch: Box<Expr<'a>>,
+        else_branch: Box<Expr<'a>>,
+    ) -> Self {
+        Self::Ternary {
+            condition,
+            then_branch,
+            else_branch,
+        }
+    }
+    pub fn new_coalesce(left: Box<Expr<'a>>, operator: Token<'a>, right: Box<Expr<'a>>) -> Self {
+        Self::Coalesce {
+            left,
+            operator,
+            right,
+        }
+    }
 
-    pub fn accept<T>(&self, visitor: &mut impl Visitor<T>) -> T {
+    pub fn new_range(start: Box<Expr<'a>>, operator: Token<'a>, end: Box<Expr<'a>>) -> Self {
+        Self::Range {
+            start,
+            operator,
+            end,
+        }
+    }
+    pub fn new_list(bracket: Token<'a>, elements: Vec<Expr<'a>>) -> Self {
+        Self::List { bracket, elements }
+    }
+    pub fn new_map(brace: Token<'a>, entries: Vec<(Expr<'a>, Expr<'a>)>) -> Self {
+        Self::Map { brace, entries }
+    }
+    pub fn new_index(object: Box<Expr<'a>>, bracket: Token<'a>, index: Box<Expr<'a>>) -> Self {
+        Self::Index {
+            object,
+            bracket,
+            index,
+        }
+    }
+    pub fn new_slice(
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        start: Option<Box<Expr<'a>>>,
+        end: Option<Box<Expr<'a>>>,
+    ) -> Self {
+        Self::Slice {
+            object,
+            bracket,
+            start,
+            end,
+        }
+    }
+    pub fn new_set_index(
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+        operator: Token<'a>,
+        value: Box<Expr<'a>>,
+    ) -> Self {
+        Self::SetIndex {
+            object,
+            bracket,
+            index,
+            operator,
+            value,
+        }
+    }
+    pub fn new_increment_index(
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+        operator: Token<'a>,
+        prefix: bool,
+    ) -> Self {
+        Self::IncrementIndex {
+            object,
+            bracket,
+            index,
+            operator,
+            prefix,
+        }
+    }
+    pub fn new_invoke(
+        object: Box<Expr<'a>>,
+        name: Token<'a>,
+        arguments: Vec<Expr<'a>>,
+        optional: bool,
+    ) -> Self {
+        Self::Invoke {
+            object,
+            name,
+            arguments,
+            optional,
+        }
+    }
+    pub fn new_throw(keyword: Token<'a>, value: Box<Expr<'a>>) -> Self {
+        Self::Throw { keyword, value }
+    }
+    pub fn new_variable(name: Token<'a>, slot: usize) -> Self {
+        Self::Variable { name, slot }
+    }
+    pub fn new_block(brace: Token<'a>, expressions: Vec<Expr<'a>>) -> Self {
+        Self::Block { brace, expressions }
+    }
+    pub fn new_for(
+        label: Option<Token<'a>>,
+        keyword: Token<'a>,
+        variable: Token<'a>,
+        iterable: Box<Expr<'a>>,
+        body: Box<Expr<'a>>,
+        collect: bool,
+    ) -> Self {
+        Self::For {
+            label,
+            keyword,
+            variable,
+            iterable,
+            body,
+            collect,
+        }
+    }
+    pub fn new_break(keyword: Token<'a>, label: Option<Token<'a>>, loops: usize) -> Self {
+        Self::Break {
+            keyword,
+            label,
+            loops,
+        }
+    }
+    pub fn new_continue(keyword: Token<'a>, label: Option<Token<'a>>, loops: usize) -> Self {
+        Self::Continue {
+            keyword,
+            label,
+            loops,
+        }
+    }
+    pub fn new_try(
+        keyword: Token<'a>,
+        body: Box<Expr<'a>>,
+        handler: Option<(Token<'a>, Box<Expr<'a>>)>,
+        finally: Option<Box<Expr<'a>>>,
+    ) -> Self {
+        Self::Try {
+            keyword,
+            body,
+            handler,
+            finally,
+        }
+    }
+
+    pub fn accept<T>(&self, visitor: &mut impl Visitor<'a, T>) -> T {
         match self {
             Expr::Binary {
                 left,
@@ -49,6 +299,90 @@ impl Expr {
             Expr::Grouping { expression } => visitor.visit_grouping_expr(expression),
             Expr::Literal { value } => visitor.visit_literal_expr(value),
             Expr::Unary { operator, right } => visitor.visit_unary_expr(operator, right),
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => visitor.visit_ternary_expr(condition, then_branch, else_branch),
+            Expr::Coalesce {
+                left,
+                operator,
+                right,
+            } => visitor.visit_coalesce_expr(left, operator, right),
+            Expr::Range {
+                start,
+                operator,
+                end,
+            } => visitor.visit_range_expr(start, operator, end),
+            Expr::List { bracket, elements } => visitor.visit_list_expr(bracket, elements),
+            Expr::Map { brace, entries } => visitor.visit_map_expr(brace, entries),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => visitor.visit_index_expr(object, bracket, index),
+            Expr::Slice {
+                object,
+                bracket,
+                start,
+                end,
+            } => visitor.visit_slice_expr(object, bracket, start.as_deref(), end.as_deref()),
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+            } => visitor.visit_set_index_expr(object, bracket, index, operator, value),
+            Expr::IncrementIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                prefix,
+            } => visitor.visit_increment_index_expr(object, bracket, index, operator, *prefix),
+            Expr::Invoke {
+                object,
+                name,
+                arguments,
+                optional,
+            } => visitor.visit_invoke_expr(object, name, arguments, *optional),
+            Expr::Throw { keyword, value } => visitor.visit_throw_expr(keyword, value),
+            Expr::Variable { name, slot } => visitor.visit_variable_expr(name, *slot),
+            Expr::Block { brace, expressions } => visitor.visit_block_expr(brace, expressions),
+            Expr::For {
+                label,
+                keyword,
+                variable,
+                iterable,
+                body,
+                collect,
+            } => {
+                visitor.visit_for_expr(label.as_ref(), keyword, variable, iterable, body, *collect)
+            }
+            Expr::Break {
+                keyword,
+                label,
+                loops,
+            } => visitor.visit_break_expr(keyword, label.as_ref(), *loops),
+            Expr::Continue {
+                keyword,
+                label,
+                loops,
+            } => visitor.visit_continue_expr(keyword, label.as_ref(), *loops),
+            Expr::Try {
+                keyword,
+                body,
+                handler,
+                finally,
+            } => visitor.visit_try_expr(
+                keyword,
+                body,
+                handler
+                    .as_ref()
+                    .map(|(variable, handler)| (variable, handler.as_ref())),
+                finally.as_deref(),
+            ),
         }
     }
 }
@@ -0,0 +1,311 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    err::{ErrorCode, Span, StrixError},
+    messages::{Msg, tr},
+    value::{HashKey, List, Map, Value},
+};
+
+/// How many bytes the heap may hold before its first collection.
+pub const INITIAL_THRESHOLD: usize = 1 << 20;
+
+/// How far the heap may grow past what survived a collection before the next one runs.
+const GROWTH_FACTOR: usize = 2;
+
+/// What a `Heap` has done so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: usize,
+    /// Lists and maps created by the script.
+    pub allocated: usize,
+    /// Lists and maps freed by the collector, which reference counting alone never frees.
+    pub collected: usize,
+    /// The estimated size of the heap, exact as of the last collection.
+    pub heap_bytes: usize,
+    pub peak_bytes: usize,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gc: {} collections, {} allocated, {} collected, heap {} bytes (peak {} bytes)",
+            self.collections, self.allocated, self.collected, self.heap_bytes, self.peak_bytes
+        )
+    }
+}
+
+/// A list or map the heap tracks. The reference is weak, so tracking keeps nothing alive.
+enum Object {
+    List(Weak<RefCell<List>>),
+    Map(Weak<RefCell<Map>>),
+}
+
+impl Object {
+    fn upgrade(&self) -> Option<Value> {
+        match self {
+            Object::List(list) => list.upgrade().map(Value::List),
+            Object::Map(map) => map.upgrade().map(Value::Map),
+        }
+    }
+}
+
+/// Tracks the lists and maps a script creates and frees those that are no longer reachable.
+///
+/// Values are reference counted, which frees most of them as soon as they are dropped but
+/// never frees a cycle. A collection marks everything reachable from the roots and empties
+/// the tracked objects it did not reach, which breaks their cycles so that reference counting
+/// frees them. The roots are the values an engine passes in, such as the VM stack, and any
+/// object referenced from outside the heap, such as a value the tree-walking interpreter holds
+/// while evaluating an expression. An object counts as referenced from outside when it has
+/// more references than the tracked objects account for.
+pub struct Heap {
+    /// Tracked objects by address. A freed object keeps its address reserved for as long as
+    /// a weak reference to it exists, so addresses are never reused while tracked.
+    objects: HashMap<usize, Object>,
+    next_gc: usize,
+    max_bytes: Option<usize>,
+    stress: bool,
+    stats: GcStats,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objects: HashMap::new(),
+            next_gc: INITIAL_THRESHOLD,
+            max_bytes: None,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+
+    /// Limits how large the heap may grow before an out of memory error. There is no limit
+    /// by default.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Collects at every allocation, which makes bugs in root handling show up at once.
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// Starts tracking `value` if it is a list or map the heap has not seen before.
+    pub fn track(&mut self, value: &Value) {
+        let Some(address) = address(value) else {
+            return;
+        };
+        if self.objects.contains_key(&address) {
+            return;
+        }
+
+        let object = match value {
+            Value::List(list) => Object::List(Rc::downgrade(list)),
+            Value::Map(map) => Object::Map(Rc::downgrade(map)),
+            _ => return,
+        };
+        self.objects.insert(address, object);
+
+        self.stats.allocated += 1;
+        self.stats.heap_bytes += size(value);
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.heap_bytes);
+    }
+
+    /// Counts the change in size of a tracked list or map that measured `before` bytes, such as
+    /// a list that `push` grew. Engines call `collect_if_needed` after it, as after creating a
+    /// list or map, so that growing one is held to the limit too.
+    pub fn resize(&mut self, value: &Value, before: usize) {
+        if !address(value).is_some_and(|address| self.objects.contains_key(&address)) {
+            return;
+        }
+
+        self.stats.heap_bytes = (self.stats.heap_bytes + size(value)).saturating_sub(before);
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.heap_bytes);
+    }
+
+    /// Collects once the heap has grown enough since the last collection, or always in stress
+    /// mode, then fails if the heap is still over its limit. Engines call this right after
+    /// creating a list or map, while every value they hold is reachable.
+    pub fn collect_if_needed(&mut self, roots: &[Value], span: Span) -> Result<(), StrixError> {
+        let max_bytes = self.max_bytes.unwrap_or(usize::MAX);
+        if self.stress || self.stats.heap_bytes > self.next_gc.min(max_bytes) {
+            self.collect(roots);
+        }
+
+        if self.stats.heap_bytes > max_bytes {
+            return Err(StrixError::new(
+                ErrorCode::OutOfMemory,
+                tr(Msg::OutOfMemory, &[&max_bytes]),
+            )
+            .with_span(span)
+            .with_note(tr(Msg::RaiseMaxHeap, &[])));
+        }
+
+        Ok(())
+    }
+
+    /// Frees every tracked object that is not reachable from `roots` or from outside the heap.
+    pub fn collect(&mut self, roots: &[Value]) {
+        // Hold every live object until the collection is done. Objects that reference counting
+        // already freed are forgotten.
+        let mut live = HashMap::new();
+        self.objects
+            .retain(|&address, object| match object.upgrade() {
+                Some(value) => {
+                    live.insert(address, value);
+                    true
+                }
+                None => false,
+            });
+
+        // Iterators and caught errors are not tracked, but lists and maps can reference each
+        // other through them, so the ones the tracked objects reach count as objects too.
+        let mut pending: Vec<Value> = live.values().cloned().collect();
+        while let Some(value) = pending.pop() {
+            for_each_child(&value, |child| {
+                if let Value::Iterator(_) | Value::Error(_) = child
+                    && let Some(address) = address(child)
+                    && !live.contains_key(&address)
+                {
+                    live.insert(address, child.clone());
+                    pending.push(child.clone());
+                }
+            });
+        }
+
+        let mut internal: HashMap<usize, usize> = HashMap::new();
+        for value in live.values() {
+            for_each_child(value, |child| {
+                if let Some(address) = address(child) {
+                    *internal.entry(address).or_default() += 1;
+                }
+            });
+        }
+
+        // One reference to each object is the one held in `live`.
+        let external: Vec<Value> = live
+            .iter()
+            .filter(|&(address, value)| {
+                strong_count(value) > 1 + internal.get(address).copied().unwrap_or(0)
+            })
+            .map(|(_, value)| value.clone())
+            .collect();
+
+        let mut marked = HashSet::new();
+        let mut pending: Vec<Value> = roots.iter().cloned().chain(external).collect();
+        while let Some(value) = pending.pop() {
+            let Some(marking) = address(&value) else {
+                continue;
+            };
+            if marked.insert(marking) {
+                for_each_child(&value, |child| {
+                    if address(child).is_some() {
+                        pending.push(child.clone());
+                    }
+                });
+            }
+        }
+
+        let mut heap_bytes = 0;
+        let mut garbage = vec![];
+        for (address, value) in live {
+            if !self.objects.contains_key(&address) {
+                continue;
+            }
+            if marked.contains(&address) {
+                heap_bytes += size(&value);
+            } else {
+                self.objects.remove(&address);
+                garbage.push(value);
+            }
+        }
+
+        // Emptying the unreachable objects breaks the cycles between them, so they are freed
+        // once `garbage` is dropped.
+        for value in &garbage {
+            match value {
+                Value::List(list) => list.borrow_mut().clear(),
+                Value::Map(map) => map.borrow_mut().clear(),
+                _ => {}
+            }
+        }
+
+        self.stats.collections += 1;
+        self.stats.collected += garbage.len();
+        self.stats.heap_bytes = heap_bytes;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(heap_bytes);
+        self.next_gc = (heap_bytes * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+    }
+}
+
+/// Identifies a list, map, iterator or caught error by the address of its contents, which
+/// its copies share.
+fn address(value: &Value) -> Option<usize> {
+    match value {
+        Value::List(list) => Some(Rc::as_ptr(list) as *const () as usize),
+        Value::Map(map) => Some(Rc::as_ptr(map) as *const () as usize),
+        Value::Iterator(iter) => Some(iter.identity().0),
+        Value::Error(caught) => Some(caught.identity().0),
+        _ => None,
+    }
+}
+
+fn strong_count(value: &Value) -> usize {
+    match value {
+        Value::List(list) => Rc::strong_count(list),
+        Value::Map(map) => Rc::strong_count(map),
+        Value::Iterator(iter) => iter.identity().1,
+        Value::Error(caught) => caught.identity().1,
+        _ => 0,
+    }
+}
+
+fn for_each_child(value: &Value, mut f: impl FnMut(&Value)) {
+    match value {
+        Value::List(list) => list.borrow().iter().for_each(f),
+        Value::Map(map) => {
+            for (key, value) in map.borrow().iter() {
+                f(key);
+                f(value);
+            }
+        }
+        Value::Iterator(iter) => {
+            if let Some(source) = iter.source() {
+                f(&source);
+            }
+        }
+        Value::Error(caught) => {
+            if let Some(value) = caught.value() {
+                f(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Estimates the memory a list or map uses, counting its reference counts, its cell and one
+/// slot per element or entry.
+pub fn size(value: &Value) -> usize {
+    let header = 2 * mem::size_of::<usize>();
+    match value {
+        Value::List(list) => {
+            header + mem::size_of::<RefCell<List>>() + list.borrow().len() * mem::size_of::<Value>()
+        }
+        Value::Map(map) => {
+            let entry =
+                2 * mem::size_of::<Value>() + mem::size_of::<HashKey>() + mem::size_of::<usize>();
+            header + mem::size_of::<RefCell<Map>>() + map.borrow().len() * entry
+        }
+        _ => 0,
+    }
+}
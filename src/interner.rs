@@ -0,0 +1,43 @@
+use std::{collections::HashMap, rc::Rc};
+
+/// The id of an interned string. Symbols from the same `Interner` are equal exactly when
+/// their strings are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// A symbol table that stores each distinct string once, so that names compare by id and
+/// repeated strings share one allocation.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: Rc<str> = Rc::from(string);
+        self.strings.push(string.clone());
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// The string of a symbol from this table, shared with every other use of it.
+    pub fn resolve(&self, symbol: Symbol) -> &Rc<str> {
+        &self.strings[symbol.0 as usize]
+    }
+
+    /// Interns a string and returns its shared copy.
+    pub fn share(&mut self, string: &str) -> Rc<str> {
+        let symbol = self.intern(string);
+        self.resolve(symbol).clone()
+    }
+}
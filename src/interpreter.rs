@@ -1,132 +1,1117 @@
+use std::{cell::RefCell, rc::Rc};
+
 use crate::{
-    err::StrixError,
+    err::{ErrorCode, Span, StackFrame, StrixError},
     expr::Expr,
+    gc::{self, GcStats, Heap},
+    interner::Interner,
+    messages::{Msg, tr},
+    suggest,
     tokenizer::{Literal, Token, TokenType},
+    value::{Caught, HashKey, Iter, List, Map, Range, Value},
     visitor::Visitor,
 };
 
-pub struct Interpreter;
+/// How many calls may be active at once before a stack overflow, unless configured otherwise.
+///
+/// Scripts cannot define functions yet, so only the script itself and one built-in method are
+/// ever active at once: method arguments are evaluated before the call, and built-in methods
+/// do not call back into the script. Until functions exist, the limit is only reached when it
+/// is set below 2.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+pub struct Interpreter {
+    calls: CallStack,
+    /// String literals, so that evaluating one again reuses its storage.
+    strings: Interner,
+    heap: Heap,
+    /// The loop variables in scope, by slot.
+    locals: Vec<Value>,
+}
 
 impl Interpreter {
+    #[allow(dead_code)]
     pub fn new() -> Self {
-        Self
+        Self::with_file("<script>".to_string())
     }
 
-    pub fn interpret(&mut self, expression: &Expr) -> Result<Literal, StrixError> {
-        self.evaluate(expression)
+    pub fn with_file(file: String) -> Self {
+        Self {
+            calls: CallStack::new(file),
+            strings: Interner::new(),
+            heap: Heap::new(),
+            locals: vec![],
+        }
+    }
+
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.calls.max_depth = max_call_depth;
+    }
+
+    pub fn set_max_heap(&mut self, max_heap: Option<usize>) {
+        self.heap.set_max_bytes(max_heap);
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Literal, StrixError> {
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.heap.set_stress(gc_stress);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    pub fn interpret(&mut self, expression: &Expr) -> Result<Value, StrixError> {
+        self.calls.reset();
+        self.locals.clear();
+        match self.evaluate(expression) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Error(err)) => Err(self.calls.trace(err)),
+            Err(Unwind::Throw(thrown)) => Err(self.calls.trace(thrown.error().clone())),
+            Err(Unwind::Break(_) | Unwind::Continue(_)) => {
+                unreachable!("the parser only accepts 'break' and 'continue' inside loops")
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, Unwind> {
         expr.accept(self)
     }
 
-    fn is_truthy(&self, value: &Literal) -> bool {
-        match value {
-            Literal::Nil => false,
-            Literal::Bool(b) => *b,
-            _ => true,
+    /// Evaluates a `catch` clause's handler with the value it receives for `caught`.
+    fn catch(&mut self, caught: Caught, handler: &Expr) -> Result<Value, Unwind> {
+        // The parser gave the caught value the next free slot.
+        let scope = self.locals.len();
+        self.locals.push(self::caught(caught));
+        let result = self.evaluate(handler);
+        self.locals.truncate(scope);
+        result
+    }
+
+    /// Evaluates `body` once per value of `iter`, storing each value in the variable at `slot`
+    /// and appending the body's value to `list`, if the loop collects one. A `break` stops the
+    /// loop, keeping the values collected so far, and a `continue` skips to the next value
+    /// without collecting one. Either passes on outward when it targets an outer loop.
+    fn iterate(
+        &mut self,
+        iter: &Iter,
+        slot: usize,
+        body: &Expr,
+        list: Option<&Value>,
+        span: Span,
+    ) -> Result<(), Unwind> {
+        while let Some(value) = iter.next() {
+            self.locals[slot] = value;
+            match self.evaluate(body) {
+                Ok(value) => {
+                    if let Some(list) = list {
+                        self.append(list, value, span)?;
+                    }
+                }
+                Err(Unwind::Break(0)) => break,
+                Err(Unwind::Continue(0)) => {}
+                Err(Unwind::Break(loops)) => return Err(Unwind::Break(loops - 1)),
+                Err(Unwind::Continue(loops)) => return Err(Unwind::Continue(loops - 1)),
+                Err(err) => return Err(err),
+            }
         }
+
+        Ok(())
+    }
+
+    /// Appends a value to the list a `for` loop collects.
+    fn append(&mut self, list: &Value, value: Value, span: Span) -> Result<(), StrixError> {
+        let before = gc::size(list);
+        if let Value::List(values) = list {
+            values.borrow_mut().push(value);
+        }
+        self.resized(list, before, span)
+    }
+
+    /// Hands a value the script may have just created to the heap. The values being evaluated
+    /// live on the native stack, where the heap finds them as references from outside it.
+    fn allocate(&mut self, value: Value, span: Span) -> Result<Value, StrixError> {
+        self.heap.track(&value);
+        self.heap.collect_if_needed(&[], span)?;
+        Ok(value)
+    }
+
+    /// Tells the heap that a list or map that measured `before` bytes may have changed size.
+    fn resized(&mut self, value: &Value, before: usize, span: Span) -> Result<(), StrixError> {
+        self.heap.resize(value, before);
+        self.heap.collect_if_needed(&[], span)
+    }
+}
+
+/// Why evaluating an expression stopped without a value: an error, or a `break` or
+/// `continue` on its way to the loop it targets, counted in loops out from the innermost one.
+/// An error raised by `throw` keeps the thrown value for the `catch` clause that handles it.
+pub enum Unwind {
+    Error(StrixError),
+    Throw(Caught),
+    Break(usize),
+    Continue(usize),
+}
+
+impl From<StrixError> for Unwind {
+    fn from(err: StrixError) -> Self {
+        Self::Error(err)
     }
 }
 
-impl Visitor<Result<Literal, StrixError>> for Interpreter {
+/// The calls active while running a script, shared by both engines for stack traces and
+/// the call-depth limit.
+pub struct CallStack {
+    file: String,
+    frames: Vec<StackFrame>,
+    pub max_depth: usize,
+}
+
+impl CallStack {
+    pub fn new(file: String) -> Self {
+        Self {
+            file,
+            frames: vec![],
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Starts over with only the top-level script active.
+    pub fn reset(&mut self) {
+        self.frames = vec![StackFrame::new(
+            "<script>".to_string(),
+            self.file.clone(),
+            1,
+        )];
+    }
+
+    /// Enters a call to `receiver.name`, failing once too many calls are active.
+    pub fn enter(&mut self, receiver: &Value, name: &Token) -> Result<(), StrixError> {
+        if self.frames.len() >= self.max_depth {
+            return Err(self.trace(
+                StrixError::at(
+                    ErrorCode::StackOverflow,
+                    name,
+                    tr(Msg::StackOverflow, &[&self.max_depth]),
+                )
+                .with_note(tr(Msg::RaiseMaxCallDepth, &[])),
+            ));
+        }
+
+        if let Some(caller) = self.frames.last_mut() {
+            caller.line = name.span.line;
+        }
+        self.frames.push(StackFrame::new(
+            format!("{}.{}", receiver.type_name(), name.lexeme),
+            self.file.clone(),
+            name.span.line,
+        ));
+        Ok(())
+    }
+
+    pub fn exit(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Attaches the active call stack, innermost call first, to an error that has none yet.
+    pub fn trace(&self, err: StrixError) -> StrixError {
+        if !err.stack.is_empty() {
+            return err;
+        }
+
+        let mut stack: Vec<StackFrame> = self.frames.iter().rev().cloned().collect();
+        if let (Some(innermost), Some(span)) = (stack.first_mut(), err.span) {
+            innermost.line = span.line;
+        }
+
+        err.with_stack(stack)
+    }
+}
+
+impl Visitor<'_, Result<Value, Unwind>> for Interpreter {
     fn visit_binary_expr(
         &mut self,
-        left: &Box<Expr>,
-        operator: &crate::tokenizer::Token,
-        right: &Box<Expr>,
-    ) -> Result<Literal, StrixError> {
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<Value, Unwind> {
         let left_val = self.evaluate(left)?;
         let right_val = self.evaluate(right)?;
 
-        match operator.token_type {
-            TokenType::Minus => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a - b).map(Literal::Number)
-            }
-            TokenType::Slash => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a / b).map(Literal::Number)
-            }
-            TokenType::Star => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a * b).map(Literal::Number)
-            }
-            TokenType::Plus => match (left_val, right_val) {
-                (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Number(l + r)),
-                (Literal::String(l), Literal::String(r)) => Ok(Literal::String(l + &r)),
-                _ => Err(StrixError::InterpreterError(
-                    operator.clone(),
-                    "The operands must be two numbers or two strings.".to_string(),
-                )),
-            },
-
-            // Operadores de Comparação
-            TokenType::Greater => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a > b).map(Literal::Bool)
-            }
-            TokenType::GreaterEqual => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a >= b).map(Literal::Bool)
-            }
-            TokenType::Less => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a < b).map(Literal::Bool)
-            }
-            TokenType::LessEqual => {
-                number_op(operator.clone(), left_val, right_val, |a, b| a <= b).map(Literal::Bool)
-            }
+        Ok(binary_op(operator, left_val, right_val)?)
+    }
 
-            // Operadores de Igualdade
-            TokenType::BangEqual => Ok(Literal::Bool(!is_equal(left_val, right_val))),
-            TokenType::EqualEqual => Ok(Literal::Bool(is_equal(left_val, right_val))),
+    fn visit_grouping_expr(&mut self, expression: &Expr) -> Result<Value, Unwind> {
+        self.evaluate(expression)
+    }
 
-            _ => unreachable!(),
+    fn visit_literal_expr(&mut self, value: &Literal) -> Result<Value, Unwind> {
+        match value {
+            Literal::String(s) => Ok(Value::String(self.strings.share(s))),
+            literal => Ok(Value::from(literal.clone())),
         }
     }
 
-    fn visit_grouping_expr(&mut self, expression: &Box<Expr>) -> Result<Literal, StrixError> {
-        self.evaluate(&expression)
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<Value, Unwind> {
+        let right_val = self.evaluate(right)?;
+
+        Ok(unary_op(operator, right_val)?)
     }
 
-    fn visit_literal_expr(&mut self, value: &Literal) -> Result<Literal, StrixError> {
-        Ok(value.clone())
+    fn visit_ternary_expr(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> Result<Value, Unwind> {
+        let condition_val = self.evaluate(condition)?;
+
+        if is_truthy(&condition_val) {
+            self.evaluate(then_branch)
+        } else {
+            self.evaluate(else_branch)
+        }
+    }
+
+    fn visit_coalesce_expr(
+        &mut self,
+        left: &Expr,
+        _operator: &Token,
+        right: &Expr,
+    ) -> Result<Value, Unwind> {
+        match self.evaluate(left)? {
+            Value::Nil => self.evaluate(right),
+            value => Ok(value),
+        }
+    }
+
+    fn visit_range_expr(
+        &mut self,
+        start: &Expr,
+        operator: &Token,
+        end: &Expr,
+    ) -> Result<Value, Unwind> {
+        let start_val = self.evaluate(start)?;
+        let end_val = self.evaluate(end)?;
+
+        Ok(range(operator, start_val, end_val)?)
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token, elements: &[Expr]) -> Result<Value, Unwind> {
+        let mut values = Vec::with_capacity(elements.len());
+        for element in elements {
+            values.push(self.evaluate(element)?);
+        }
+
+        Ok(self.allocate(Value::new_list(values), bracket.span)?)
+    }
+
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<Value, Unwind> {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            let key_val = self.evaluate(key)?;
+            let value_val = self.evaluate(value)?;
+            map.insert(hash_key(brace, &key_val)?, key_val, value_val);
+        }
+
+        Ok(self.allocate(Value::new_map(map), brace.span)?)
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<Value, Unwind> {
+        let object_val = self.evaluate(object)?;
+        let index_val = self.evaluate(index)?;
+
+        Ok(get_index(bracket, &object_val, &index_val)?)
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+    ) -> Result<Value, Unwind> {
+        let object_val = self.evaluate(object)?;
+        let start_val = match start {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+        let end_val = match end {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+
+        let slice = slice(bracket, &object_val, &start_val, &end_val)?;
+        Ok(self.allocate(slice, bracket.span)?)
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        value: &Expr,
+    ) -> Result<Value, Unwind> {
+        let object_val = self.evaluate(object)?;
+        let index_val = self.evaluate(index)?;
+        let value_val = self.evaluate(value)?;
+
+        // Assigning to a new key grows a map.
+        let before = gc::size(&object_val);
+        let result = assign_index(bracket, &object_val, index_val, operator, value_val)?;
+        self.resized(&object_val, before, bracket.span)?;
+        Ok(result)
+    }
+
+    fn visit_increment_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        operator: &Token,
+        prefix: bool,
+    ) -> Result<Value, Unwind> {
+        let object_val = self.evaluate(object)?;
+        let index_val = self.evaluate(index)?;
+
+        Ok(increment_index(
+            bracket,
+            &object_val,
+            index_val,
+            operator,
+            prefix,
+        )?)
+    }
+
+    fn visit_invoke_expr(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+        arguments: &[Expr],
+        optional: bool,
+    ) -> Result<Value, Unwind> {
+        let object_val = self.evaluate(object)?;
+        if optional && object_val == Value::Nil {
+            return Ok(Value::Nil);
+        }
+
+        let mut args = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            args.push(self.evaluate(argument)?);
+        }
+
+        // Methods such as `push` and `insert` grow the list or map they are called on.
+        let receiver = object_val.clone();
+        let before = gc::size(&receiver);
+        self.calls.enter(&object_val, name)?;
+        let result = call_method(object_val, name, args).map_err(|err| self.calls.trace(err));
+        self.calls.exit();
+
+        self.resized(&receiver, before, name.span)?;
+        Ok(self.allocate(result?, name.span)?)
+    }
+
+    fn visit_throw_expr(&mut self, keyword: &Token, value: &Expr) -> Result<Value, Unwind> {
+        let value_val = self.evaluate(value)?;
+        Err(Unwind::Throw(throw(keyword, value_val)))
+    }
+
+    fn visit_variable_expr(&mut self, _name: &Token, slot: usize) -> Result<Value, Unwind> {
+        Ok(self.locals.get(slot).cloned().unwrap_or(Value::Nil))
+    }
+
+    fn visit_block_expr(&mut self, _brace: &Token, expressions: &[Expr]) -> Result<Value, Unwind> {
+        let mut value = Value::Nil;
+        for expression in expressions {
+            value = self.evaluate(expression)?;
+        }
+
+        Ok(value)
     }
 
-    fn visit_unary_expr(
+    fn visit_for_expr(
         &mut self,
-        operator: &crate::tokenizer::Token,
-        right: &Box<Expr>,
-    ) -> Result<Literal, StrixError> {
-        let right_val = self.evaluate(&right)?;
+        _label: Option<&Token>,
+        keyword: &Token,
+        _variable: &Token,
+        iterable: &Expr,
+        body: &Expr,
+        collect: bool,
+    ) -> Result<Value, Unwind> {
+        let iterable_val = self.evaluate(iterable)?;
+        let iter = iter(keyword, &iterable_val)?;
+        let list = match collect {
+            true => Some(self.allocate(Value::new_list(vec![]), keyword.span)?),
+            false => None,
+        };
 
-        match operator.token_type {
-            TokenType::Bang => Ok(Literal::Bool(!self.is_truthy(&right_val))),
-            TokenType::Minus => match right_val {
-                Literal::Number(n) => Ok(Literal::Number(-n)),
-                _ => Err(StrixError::InterpreterError(
-                    operator.clone(),
-                    "The operator must be a number".to_string(),
-                )),
-            },
-            _ => unreachable!(),
+        // The parser gave the iterator the next free slot and the variable the one after it.
+        let scope = self.locals.len();
+        self.locals
+            .extend([Value::Iterator(iter.clone()), Value::Nil]);
+        let result = self.iterate(&iter, scope + 1, body, list.as_ref(), keyword.span);
+        self.locals.truncate(scope);
+
+        result?;
+        Ok(list.unwrap_or(Value::Nil))
+    }
+
+    fn visit_break_expr(
+        &mut self,
+        _keyword: &Token,
+        _label: Option<&Token>,
+        loops: usize,
+    ) -> Result<Value, Unwind> {
+        Err(Unwind::Break(loops))
+    }
+
+    fn visit_continue_expr(
+        &mut self,
+        _keyword: &Token,
+        _label: Option<&Token>,
+        loops: usize,
+    ) -> Result<Value, Unwind> {
+        Err(Unwind::Continue(loops))
+    }
+
+    fn visit_try_expr(
+        &mut self,
+        _keyword: &Token,
+        body: &Expr,
+        handler: Option<(&Token, &Expr)>,
+        finally: Option<&Expr>,
+    ) -> Result<Value, Unwind> {
+        let result = match (self.evaluate(body), handler) {
+            (Err(Unwind::Error(err)), Some((_variable, handler))) if err.is_catchable() => {
+                self.catch(Caught::new(err), handler)
+            }
+            (Err(Unwind::Throw(thrown)), Some((_variable, handler))) => self.catch(thrown, handler),
+            (result, _) => result,
+        };
+
+        // Errors that cannot be caught, such as running out of memory, leave at once without
+        // cleaning up, as they do in the VM, since the cleanup could replace them.
+        if let Err(Unwind::Error(err)) = &result
+            && !err.is_catchable()
+        {
+            return result;
+        }
+
+        // An error, `break` or `continue` from the cleanup replaces the outcome of the rest.
+        if let Some(finally) = finally {
+            self.evaluate(finally)?;
         }
+        result
+    }
+}
+
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Nil => false,
+        Value::Bool(b) => *b,
+        _ => true,
     }
 }
 
-fn number_op<F, T>(op: Token, l: Literal, r: Literal, fun: F) -> Result<T, StrixError>
+pub fn binary_op(operator: &Token, left: Value, right: Value) -> Result<Value, StrixError> {
+    match operator.token_type {
+        TokenType::Minus
+        | TokenType::Slash
+        | TokenType::Star
+        | TokenType::Plus
+        | TokenType::Percent
+        | TokenType::Ampersand
+        | TokenType::Pipe
+        | TokenType::Caret
+        | TokenType::LessLess
+        | TokenType::GreaterGreater => arithmetic(operator, operator.token_type, left, right),
+
+        // Operadores de Comparação
+        TokenType::Greater => {
+            number_op(operator.clone(), left, right, |a, b| a > b).map(Value::Bool)
+        }
+        TokenType::GreaterEqual => {
+            number_op(operator.clone(), left, right, |a, b| a >= b).map(Value::Bool)
+        }
+        TokenType::Less => number_op(operator.clone(), left, right, |a, b| a < b).map(Value::Bool),
+        TokenType::LessEqual => {
+            number_op(operator.clone(), left, right, |a, b| a <= b).map(Value::Bool)
+        }
+
+        // Operadores de Igualdade
+        TokenType::BangEqual => Ok(Value::Bool(!is_equal(left, right))),
+        TokenType::EqualEqual => Ok(Value::Bool(is_equal(left, right))),
+
+        _ => Err(StrixError::at(
+            ErrorCode::UnsupportedOperator,
+            operator,
+            tr(Msg::UnsupportedBinaryOperator, &[&operator.lexeme]),
+        )),
+    }
+}
+
+pub fn unary_op(operator: &Token, right: Value) -> Result<Value, StrixError> {
+    match operator.token_type {
+        TokenType::Bang => Ok(Value::Bool(!is_truthy(&right))),
+        TokenType::Minus => match right {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            _ => Err(StrixError::at(
+                ErrorCode::OperandMustBeNumber,
+                operator,
+                tr(Msg::OperandMustBeNumber, &[]),
+            )),
+        },
+        _ => Err(StrixError::at(
+            ErrorCode::UnsupportedOperator,
+            operator,
+            tr(Msg::UnsupportedUnaryOperator, &[&operator.lexeme]),
+        )),
+    }
+}
+
+pub fn range(operator: &Token, start: Value, end: Value) -> Result<Value, StrixError> {
+    match (integer(&start), integer(&end)) {
+        (Some(start), Some(end)) => Ok(Value::Range(Range::new(
+            start,
+            end,
+            operator.token_type == TokenType::DotDotEqual,
+        ))),
+        _ => Err(StrixError::at(
+            ErrorCode::InvalidRangeBounds,
+            operator,
+            tr(Msg::InvalidRangeBounds, &[]),
+        )),
+    }
+}
+
+/// Copies part of a list. Nil bounds stand for the start and the end of the list.
+pub fn slice(
+    bracket: &Token,
+    object: &Value,
+    start: &Value,
+    end: &Value,
+) -> Result<Value, StrixError> {
+    let Value::List(list) = object else {
+        return Err(StrixError::at(
+            ErrorCode::NotSliceable,
+            bracket,
+            tr(Msg::NotSliceable, &[]),
+        ));
+    };
+
+    let elements = list.borrow();
+    let len = elements.len();
+    let from = slice_bound(bracket, len, start, 0)?;
+    let to = slice_bound(bracket, len, end, len)?.max(from);
+
+    Ok(Value::new_list(elements[from..to].to_vec()))
+}
+
+/// Stores into an indexed element with `=` or a compound assignment, returning the new value.
+pub fn assign_index(
+    bracket: &Token,
+    object: &Value,
+    index: Value,
+    operator: &Token,
+    value: Value,
+) -> Result<Value, StrixError> {
+    let new_val = if operator.token_type == TokenType::Equal {
+        value
+    } else {
+        let current = get_index(bracket, object, &index)?;
+        compound_op(operator, current, value)?
+    };
+
+    set_index(bracket, object, index, new_val.clone())?;
+    Ok(new_val)
+}
+
+pub fn increment_index(
+    bracket: &Token,
+    object: &Value,
+    index: Value,
+    operator: &Token,
+    prefix: bool,
+) -> Result<Value, StrixError> {
+    let old_val = get_index(bracket, object, &index)?;
+    let new_val = compound_op(operator, old_val.clone(), Value::Number(1.0))?;
+    set_index(bracket, object, index, new_val.clone())?;
+
+    Ok(if prefix { new_val } else { old_val })
+}
+
+pub fn call_method(object: Value, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    match object {
+        Value::List(list) => list_method(&list, name, args),
+        Value::Map(map) => map_method(&map, name, args),
+        Value::Range(range) => range_method(range, name, args),
+        Value::String(s) => string_method(s, name, args),
+        Value::Iterator(iter) => iterator_method(&iter, name, args),
+        Value::Error(caught) => error_method(&caught, name, args),
+        value => Err(StrixError::at(
+            ErrorCode::UndefinedMethod,
+            name,
+            tr(Msg::NoMethods, &[&value.type_name()]),
+        )),
+    }
+}
+
+/// Starts iterating over a value for a `for` loop or `iter()`.
+pub fn iter(token: &Token, value: &Value) -> Result<Iter, StrixError> {
+    Iter::new(value).ok_or_else(|| {
+        StrixError::at(
+            ErrorCode::NotIterable,
+            token,
+            tr(Msg::NotIterable, &[&value.type_name()]),
+        )
+        .with_note(tr(Msg::IterableValues, &[]))
+    })
+}
+
+pub fn throw(keyword: &Token, value: Value) -> Caught {
+    // Throwing a caught error raises it again as it was.
+    if let Value::Error(caught) = value {
+        return caught;
+    }
+
+    let err = StrixError::at(
+        ErrorCode::UncaughtThrow,
+        keyword,
+        tr(Msg::UncaughtThrow, &[&value]),
+    )
+    .with_value(value.to_string());
+    Caught::thrown(err, value)
+}
+
+/// The value a `catch` clause receives for an error: the thrown value for a `throw`, or the
+/// error itself for an error raised by the language.
+pub fn caught(caught: Caught) -> Value {
+    match caught.value() {
+        Some(value) => value.clone(),
+        None => Value::Error(caught),
+    }
+}
+
+fn number_op<F, T>(op: Token, l: Value, r: Value, fun: F) -> Result<T, StrixError>
 where
     F: Fn(f64, f64) -> T,
 {
-    if let (Literal::Number(left_num), Literal::Number(right_num)) = (l, r) {
+    if let (Value::Number(left_num), Value::Number(right_num)) = (l, r) {
         Ok(fun(left_num, right_num))
     } else {
-        Err(StrixError::InterpreterError(
-            op,
-            "Operands must be numbers.".to_string(),
+        Err(StrixError::at(
+            ErrorCode::OperandsMustBeNumbers,
+            &op,
+            tr(Msg::OperandsMustBeNumbers, &[]),
         ))
     }
 }
 
-fn is_equal(a: Literal, b: Literal) -> bool {
+fn integer_op<F>(op: &Token, l: Value, r: Value, fun: F) -> Result<Value, StrixError>
+where
+    F: Fn(i64, i64) -> Option<i64>,
+{
+    let result = match (integer(&l), integer(&r)) {
+        (Some(left_num), Some(right_num)) => fun(left_num, right_num),
+        _ => None,
+    };
+
+    match result {
+        Some(n) => Ok(Value::Number(n as f64)),
+        None => Err(StrixError::at(
+            ErrorCode::OperandsMustBeIntegers,
+            op,
+            tr(Msg::OperandsMustBeIntegers, &[]),
+        )),
+    }
+}
+
+/// Returns the value as an integer, if it is a number without a fractional part.
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn add(op: &Token, l: Value, r: Value) -> Result<Value, StrixError> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+        (Value::String(l), Value::String(r)) => Ok(Value::String([l, r].concat().into())),
+        _ => Err(StrixError::at(
+            ErrorCode::InvalidAddOperands,
+            op,
+            tr(Msg::InvalidAddOperands, &[]),
+        )),
+    }
+}
+
+/// Applies an arithmetic or bitwise `operation`, reporting errors at `op`, which is either
+/// the binary operator itself or the compound assignment or increment it stands for.
+fn arithmetic(op: &Token, operation: TokenType, l: Value, r: Value) -> Result<Value, StrixError> {
+    match operation {
+        TokenType::Plus => add(op, l, r),
+        TokenType::Minus => number_op(op.clone(), l, r, |a, b| a - b).map(Value::Number),
+        TokenType::Star => number_op(op.clone(), l, r, |a, b| a * b).map(Value::Number),
+        TokenType::Slash => number_op(op.clone(), l, r, |a, b| a / b).map(Value::Number),
+        TokenType::Percent => remainder(op, l, r),
+        TokenType::Ampersand => integer_op(op, l, r, |a, b| Some(a & b)),
+        TokenType::Pipe => integer_op(op, l, r, |a, b| Some(a | b)),
+        TokenType::Caret => integer_op(op, l, r, |a, b| Some(a ^ b)),
+        TokenType::LessLess => integer_op(op, l, r, |a, b| a.checked_shl(u32::try_from(b).ok()?)),
+        TokenType::GreaterGreater => {
+            integer_op(op, l, r, |a, b| a.checked_shr(u32::try_from(b).ok()?))
+        }
+        _ => Err(StrixError::at(
+            ErrorCode::UnsupportedOperator,
+            op,
+            tr(Msg::UnsupportedBinaryOperator, &[&op.lexeme]),
+        )),
+    }
+}
+
+/// The remainder of a truncating division, which takes the sign of the dividend. Unlike
+/// division, which gives an infinity, a remainder by zero is an error rather than NaN.
+fn remainder(op: &Token, l: Value, r: Value) -> Result<Value, StrixError> {
+    let (a, b) = number_op(op.clone(), l, r, |a, b| (a, b))?;
+    if b == 0.0 {
+        return Err(StrixError::at(
+            ErrorCode::RemainderByZero,
+            op,
+            tr(Msg::RemainderByZero, &[]),
+        ));
+    }
+    Ok(Value::Number(a % b))
+}
+
+/// Applies the operation of a compound assignment (`+=`, `<<=`, ...) or increment (`++`, `--`).
+fn compound_op(op: &Token, current: Value, value: Value) -> Result<Value, StrixError> {
+    let operation = match op.token_type {
+        TokenType::PlusEqual | TokenType::PlusPlus => TokenType::Plus,
+        TokenType::MinusEqual | TokenType::MinusMinus => TokenType::Minus,
+        TokenType::StarEqual => TokenType::Star,
+        TokenType::SlashEqual => TokenType::Slash,
+        TokenType::PercentEqual => TokenType::Percent,
+        TokenType::AmpersandEqual => TokenType::Ampersand,
+        TokenType::PipeEqual => TokenType::Pipe,
+        TokenType::CaretEqual => TokenType::Caret,
+        TokenType::LessLessEqual => TokenType::LessLess,
+        TokenType::GreaterGreaterEqual => TokenType::GreaterGreater,
+        _ => {
+            return Err(StrixError::at(
+                ErrorCode::UnsupportedOperator,
+                op,
+                tr(Msg::UnsupportedAssignmentOperator, &[&op.lexeme]),
+            ));
+        }
+    };
+    arithmetic(op, operation, current, value)
+}
+
+fn is_equal(a: Value, b: Value) -> bool {
     match (a, b) {
-        (Literal::Nil, Literal::Nil) => true,
-        (Literal::Nil, _) => false,
+        (Value::Nil, Value::Nil) => true,
+        (Value::Nil, _) => false,
         (a, b) => a == b,
     }
 }
+
+pub fn hash_key(token: &Token, key: &Value) -> Result<HashKey, StrixError> {
+    HashKey::from_value(key).ok_or_else(|| {
+        StrixError::at(
+            ErrorCode::UnhashableKey,
+            token,
+            tr(Msg::UnhashableKey, &[&key.type_name()]),
+        )
+        .with_note(tr(Msg::HashableKeys, &[]))
+    })
+}
+
+pub fn get_index(bracket: &Token, object: &Value, index: &Value) -> Result<Value, StrixError> {
+    match object {
+        Value::List(list) => {
+            let elements = list.borrow();
+            let i = list_index(bracket, elements.len(), index)?;
+            Ok(elements[i].clone())
+        }
+        // Missing keys read as nil, so defaults can be supplied with `??`.
+        Value::Map(map) => Ok(map
+            .borrow()
+            .get(&hash_key(bracket, index)?)
+            .cloned()
+            .unwrap_or(Value::Nil)),
+        _ => Err(StrixError::at(
+            ErrorCode::NotIndexable,
+            bracket,
+            tr(Msg::NotIndexable, &[]),
+        )),
+    }
+}
+
+fn set_index(
+    bracket: &Token,
+    object: &Value,
+    index: Value,
+    value: Value,
+) -> Result<(), StrixError> {
+    match object {
+        Value::List(list) => {
+            let mut elements = list.borrow_mut();
+            let i = list_index(bracket, elements.len(), &index)?;
+            elements[i] = value;
+        }
+        Value::Map(map) => {
+            let hash = hash_key(bracket, &index)?;
+            map.borrow_mut().insert(hash, index, value);
+        }
+        _ => {
+            return Err(StrixError::at(
+                ErrorCode::NotIndexable,
+                bracket,
+                tr(Msg::NotIndexable, &[]),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves a possibly negative index into a position inside a list of length `len`.
+fn list_index(bracket: &Token, len: usize, index: &Value) -> Result<usize, StrixError> {
+    let n = match index {
+        Value::Number(n) if n.fract() == 0.0 => *n,
+        _ => {
+            return Err(StrixError::at(
+                ErrorCode::InvalidIndex,
+                bracket,
+                tr(Msg::ListIndexNotInteger, &[]),
+            ));
+        }
+    };
+
+    let resolved = if n < 0.0 { n + len as f64 } else { n };
+    if resolved < 0.0 || resolved >= len as f64 {
+        return Err(StrixError::at(
+            ErrorCode::IndexOutOfBounds,
+            bracket,
+            tr(Msg::IndexOutOfBounds, &[&n, &len]),
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+/// Resolves where `insert` puts an element. Negative indices count from the end as they do
+/// everywhere else, so `-1` inserts before the last element, and positions past the end
+/// append.
+fn insert_index(name: &Token, len: usize, index: &Value) -> Result<usize, StrixError> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Ok((*n as usize).min(len)),
+        index => list_index(name, len, index),
+    }
+}
+
+/// Resolves a slice bound, clamping it into `0..=len`. Missing bounds take `default`.
+fn slice_bound(
+    bracket: &Token,
+    len: usize,
+    bound: &Value,
+    default: usize,
+) -> Result<usize, StrixError> {
+    match bound {
+        Value::Nil => Ok(default),
+        Value::Number(n) if n.fract() == 0.0 => {
+            let resolved = if *n < 0.0 { n + len as f64 } else { *n };
+            Ok(resolved.clamp(0.0, len as f64) as usize)
+        }
+        _ => Err(StrixError::at(
+            ErrorCode::InvalidIndex,
+            bracket,
+            tr(Msg::SliceBoundsNotIntegers, &[]),
+        )),
+    }
+}
+
+fn check_arity(name: &Token, expected: usize, args: &[Value]) -> Result<(), StrixError> {
+    if args.len() != expected {
+        return Err(StrixError::at(
+            ErrorCode::WrongArity,
+            name,
+            tr(Msg::WrongArity, &[&expected, &args.len()]),
+        ));
+    }
+
+    Ok(())
+}
+
+const LIST_METHODS: [&str; 7] = ["push", "pop", "len", "insert", "remove", "contains", "iter"];
+const MAP_METHODS: [&str; 6] = ["keys", "values", "len", "has", "remove", "iter"];
+const RANGE_METHODS: [&str; 3] = ["len", "contains", "iter"];
+const STRING_METHODS: [&str; 1] = ["iter"];
+const ITERATOR_METHODS: [&str; 3] = ["next", "done", "iter"];
+const ERROR_METHODS: [&str; 5] = ["message", "kind", "code", "line", "column"];
+
+fn undefined_method(name: &Token, type_name: &str, methods: &[&'static str]) -> StrixError {
+    let err = StrixError::at(
+        ErrorCode::UndefinedMethod,
+        name,
+        tr(Msg::UndefinedMethod, &[&name.lexeme, &type_name]),
+    );
+
+    match suggest::did_you_mean(name.lexeme, methods.iter().copied()) {
+        Some(note) => err.with_note(note),
+        None => err,
+    }
+}
+
+fn list_method(
+    list: &Rc<RefCell<List>>,
+    name: &Token,
+    mut args: Vec<Value>,
+) -> Result<Value, StrixError> {
+    match name.lexeme {
+        "push" => {
+            check_arity(name, 1, &args)?;
+            list.borrow_mut().append(&mut args);
+            Ok(Value::Nil)
+        }
+        "pop" => {
+            check_arity(name, 0, &args)?;
+            list.borrow_mut()
+                .pop()
+                .ok_or_else(|| StrixError::at(ErrorCode::EmptyList, name, tr(Msg::EmptyList, &[])))
+        }
+        "len" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::Number(list.borrow().len() as f64))
+        }
+        "insert" => {
+            check_arity(name, 2, &args)?;
+            let value = args.pop().unwrap_or(Value::Nil);
+            let mut elements = list.borrow_mut();
+            let i = insert_index(name, elements.len(), &args[0])?;
+            elements.insert(i, value);
+            Ok(Value::Nil)
+        }
+        "remove" => {
+            check_arity(name, 1, &args)?;
+            let mut elements = list.borrow_mut();
+            let i = list_index(name, elements.len(), &args[0])?;
+            Ok(elements.remove(i))
+        }
+        "contains" => {
+            check_arity(name, 1, &args)?;
+            let needle = args.pop().unwrap_or(Value::Nil);
+            let found = list
+                .borrow()
+                .iter()
+                .any(|element| is_equal(element.clone(), needle.clone()));
+            Ok(Value::Bool(found))
+        }
+        "iter" => iter_method(name, Value::List(list.clone()), &args),
+        _ => Err(undefined_method(name, "list", &LIST_METHODS)),
+    }
+}
+
+fn map_method(map: &Rc<RefCell<Map>>, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    match name.lexeme {
+        "keys" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::new_list(map.borrow().keys().cloned().collect()))
+        }
+        "values" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::new_list(map.borrow().values().cloned().collect()))
+        }
+        "len" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::Number(map.borrow().len() as f64))
+        }
+        "has" => {
+            check_arity(name, 1, &args)?;
+            let key = hash_key(name, &args[0])?;
+            Ok(Value::Bool(map.borrow().contains_key(&key)))
+        }
+        "remove" => {
+            check_arity(name, 1, &args)?;
+            let key = hash_key(name, &args[0])?;
+            Ok(map.borrow_mut().remove(&key).unwrap_or(Value::Nil))
+        }
+        "iter" => iter_method(name, Value::Map(map.clone()), &args),
+        _ => Err(undefined_method(name, "map", &MAP_METHODS)),
+    }
+}
+
+fn range_method(range: Range, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    match name.lexeme {
+        "len" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::Number(range.len() as f64))
+        }
+        "contains" => {
+            check_arity(name, 1, &args)?;
+            Ok(Value::Bool(
+                integer(&args[0]).is_some_and(|n| range.contains(n)),
+            ))
+        }
+        "iter" => iter_method(name, Value::Range(range), &args),
+        _ => Err(undefined_method(name, "range", &RANGE_METHODS)),
+    }
+}
+
+fn string_method(s: Rc<str>, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    match name.lexeme {
+        "iter" => iter_method(name, Value::String(s), &args),
+        _ => Err(undefined_method(name, "string", &STRING_METHODS)),
+    }
+}
+
+fn iterator_method(iter: &Iter, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    match name.lexeme {
+        "next" => {
+            check_arity(name, 0, &args)?;
+            iter.next().ok_or_else(|| {
+                StrixError::at(
+                    ErrorCode::IteratorExhausted,
+                    name,
+                    tr(Msg::IteratorExhausted, &[]),
+                )
+                .with_note(tr(Msg::CheckDone, &[]))
+            })
+        }
+        "done" => {
+            check_arity(name, 0, &args)?;
+            Ok(Value::Bool(!iter.has_next()))
+        }
+        "iter" => iter_method(name, Value::Iterator(iter.clone()), &args),
+        _ => Err(undefined_method(name, "iterator", &ITERATOR_METHODS)),
+    }
+}
+
+fn error_method(caught: &Caught, name: &Token, args: Vec<Value>) -> Result<Value, StrixError> {
+    let err = caught.error();
+    let position = |n: Option<usize>| n.map_or(Value::Nil, |n| Value::Number(n as f64));
+
+    let value = match name.lexeme {
+        "message" => Value::String(err.message.as_str().into()),
+        "kind" => Value::String(err.kind.to_string().to_lowercase().into()),
+        "code" => Value::String(err.code.as_str().into()),
+        "line" => position(err.span.map(|span| span.line)),
+        "column" => position(err.span.map(|span| span.column)),
+        _ => return Err(undefined_method(name, "error", &ERROR_METHODS)),
+    };
+    check_arity(name, 0, &args)?;
+
+    Ok(value)
+}
+
+/// Starts iterating over a value, or continues an iterator, for its `iter()` method.
+fn iter_method(name: &Token, object: Value, args: &[Value]) -> Result<Value, StrixError> {
+    check_arity(name, 0, args)?;
+    iter(name, &object).map(Value::Iterator)
+}
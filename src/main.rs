@@ -1,48 +1,186 @@
 use std::{env, process};
 
-use crate::strix::Strix;
+use crate::{
+    err::ErrorCode,
+    explain::Explanation,
+    messages::Locale,
+    strix::{Engine, ErrorFormat, Strix},
+};
 
 mod ast;
+mod bytecode;
+mod chunk;
+mod compiler;
+mod disasm;
 mod err;
+mod explain;
 mod expr;
+mod gc;
+mod interner;
 mod interpreter;
+mod messages;
+mod optimizer;
 mod parser;
 mod scanner;
 mod strix;
+mod suggest;
 mod tokenizer;
+mod value;
+mod verifier;
 mod visitor;
+mod vm;
 
 fn main() {
-    let mut args: Vec<String> = env::args().collect();
-    args.remove(0);
-
+    let mut args = env::args().skip(1);
     let mut strix = Strix::new();
+    if let Some(locale) = Locale::from_env() {
+        strix.set_locale(locale);
+    }
+    let mut positional = vec![];
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-frames" => strix.set_max_frames(number_arg(args.next())),
+            "--max-call-depth" => strix.set_max_call_depth(number_arg(args.next())),
+            "--max-nesting" => strix.set_max_nesting(number_arg(args.next())),
+            "--max-heap" => strix.set_max_heap(Some(number_arg(args.next()))),
+            "--gc-stress" => strix.set_gc_stress(true),
+            "--gc-stats" => strix.set_print_gc_stats(true),
+            "--explain" => explain(args.next()),
+            "--lang" => strix.set_locale(locale_arg(args.next())),
+            "--error-format=human" => strix.set_error_format(ErrorFormat::Human),
+            "--error-format=json" => strix.set_error_format(ErrorFormat::Json),
+            _ if arg.starts_with("--error-format=") => usage(),
+            "--engine=tree" => strix.set_engine(Engine::Tree),
+            "--engine=vm" => strix.set_engine(Engine::Vm),
+            _ if arg.starts_with("--engine=") => usage(),
+            "-O" => strix.set_opt_level(optimizer::MAX_LEVEL),
+            _ if arg.starts_with("-O") => {
+                strix.set_opt_level(arg[2..].parse().unwrap_or_else(|_| usage()))
+            }
+            "--trace-exec" => {
+                strix.set_engine(Engine::Vm);
+                strix.set_trace_exec(true);
+            }
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with('-') => usage(),
+            _ => positional.push(arg),
+        }
+    }
+
+    match (positional.as_slice(), &output) {
+        ([command, path], _) if command == "compile" => strix.compile_file(path, output.as_deref()),
+        ([command, path], None) if command == "run" => strix.run_file(path),
+        ([command, path], None) if command == "disasm" => strix.disassemble_file(path),
+        ([path], None) => strix.run_file(path),
+        ([], None) => strix.run_prompt(),
+        _ => usage(),
+    }
+}
 
-    if args.len() > 1 {
-        println!("Usage: strix [script]");
-        process::exit(64);
-    } else if args.len() == 1 {
-        strix.run_file(&args[0]);
-    } else {
-        strix.run_prompt();
+fn number_arg(arg: Option<String>) -> usize {
+    arg.and_then(|n| n.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn locale_arg(arg: Option<String>) -> Locale {
+    arg.and_then(|tag| Locale::parse(&tag))
+        .unwrap_or_else(|| usage())
+}
+
+fn explain(code: Option<String>) -> ! {
+    let code = code.unwrap_or_else(|| usage());
+    match ErrorCode::parse(&code) {
+        Some(code) => {
+            println!("{}", Explanation::of(code));
+            process::exit(0);
+        }
+        None => {
+            eprintln!("Unknown error code '{code}'.");
+            process::exit(64);
+        }
     }
 }
 
+fn usage() -> ! {
+    println!(
+        "Usage: strix [--lang en|pt-BR] [--error-format=human|json] [--engine=tree|vm]\n       \
+                     [-O<level>] [--trace-exec] [--max-frames <n>] [--max-call-depth <n>]\n       \
+                     [--max-nesting <n>] [--max-heap <bytes>] [--gc-stress] [--gc-stats]\n       \
+                     [[run] script]\n       \
+         strix compile <script> [-o <output>]\n       \
+         strix disasm <script>\n       \
+         strix --explain <code>"
+    );
+    process::exit(64);
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{borrow::Cow, env, fs, process, rc::Rc};
+
     use crate::{
-        ast::AstPrinter, expr::Expr, tokenizer::{Literal, Token, TokenType}
+        ast::AstPrinter,
+        bytecode,
+        chunk::{Chunk, OpCode},
+        compiler::Compiler,
+        disasm,
+        err::{ErrorCode, ErrorKind, Span, StrixError},
+        explain::Explanation,
+        expr::Expr,
+        gc::Heap,
+        interner::Interner,
+        interpreter::{self, Interpreter},
+        messages::{self, Locale},
+        optimizer::Optimizer,
+        parser::Parser,
+        scanner::Scanner,
+        strix::{Engine, Strix},
+        suggest,
+        tokenizer::{Literal, Token, TokenType},
+        value::{Caught, HashKey, Iter, Map, Value},
+        verifier,
+        vm::Vm,
     };
 
+    fn parse(source: &str) -> Result<Expr<'_>, StrixError> {
+        Parser::new(Scanner::from(source)).parse()
+    }
+
+    /// Runs a script on the tree-walking interpreter.
+    fn eval(source: &str) -> Result<Value, StrixError> {
+        Interpreter::new().interpret(&parse(source)?)
+    }
+
+    /// Runs a script on the bytecode VM.
+    fn eval_vm(source: &str) -> Result<Value, StrixError> {
+        let chunk = Compiler::new().compile(&parse(source)?)?;
+        Vm::new().run(&chunk)
+    }
+
+    fn optimize<'a>(expr: &Expr<'a>, level: u8) -> Expr<'a> {
+        let mut optimizer = Optimizer::new();
+        optimizer.set_level(level);
+        optimizer.optimize(expr)
+    }
+
+    /// Shows a result the way the two engines are compared.
+    fn render(result: Result<Value, StrixError>) -> String {
+        match result {
+            Ok(value) => value.to_string(),
+            Err(err) => err.render(16),
+        }
+    }
+
     #[test]
     fn pretty_print_ast() {
         // Represents the expression: -123 * (45.67)
         let expression = Expr::new_binary(
             Box::new(Expr::new_unary(
-                Token::new(TokenType::Minus, "-".to_string(), None, 1),
+                Token::new(TokenType::Minus, "-", None, 1),
                 Box::new(Expr::new_literal(Literal::Number(123.0))),
             )),
-            Token::new(TokenType::Star, "*".to_string(), None, 1),
+            Token::new(TokenType::Star, "*", None, 1),
             Box::new(Expr::new_grouping(Box::new(Expr::new_literal(
                 Literal::Number(45.67),
             )))),
@@ -54,4 +192,1359 @@ mod tests {
 
         assert_eq!(result, "(* (- 123) (group 45.67))".to_string())
     }
+
+    #[test]
+    fn scan_compound_operators() {
+        let scanner = Scanner::from("+= -= *= /= %= &= |= ^= <<= >>= ++ -- < > % & | ^ << >>");
+        let types: Vec<TokenType> = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|t| t.token_type)
+            .collect();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::PlusEqual,
+                TokenType::MinusEqual,
+                TokenType::StarEqual,
+                TokenType::SlashEqual,
+                TokenType::PercentEqual,
+                TokenType::AmpersandEqual,
+                TokenType::PipeEqual,
+                TokenType::CaretEqual,
+                TokenType::LessLessEqual,
+                TokenType::GreaterGreaterEqual,
+                TokenType::PlusPlus,
+                TokenType::MinusMinus,
+                TokenType::Less,
+                TokenType::Greater,
+                TokenType::Percent,
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::LessLess,
+                TokenType::GreaterGreater,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_tokens() {
+        let mut scanner = Scanner::from("1 + // two\n 2 @");
+        let types: Vec<TokenType> = scanner
+            .by_ref()
+            .take(3)
+            .map(|t| t.unwrap().token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![TokenType::Number, TokenType::Plus, TokenType::Number]
+        );
+        assert!(scanner.next().unwrap().is_err());
+        assert!(scanner.next().is_none());
+
+        // A scan error past the end of the expression is still reported.
+        let err = parse("1 2 @").err().unwrap();
+        assert_eq!(err.code, ErrorCode::UnexpectedCharacter);
+
+        // Tokens left over after the expression are an error, reported at the first of them.
+        for (source, column) in [("1 2 3", 3), ("1 )", 3), ("[1]\n]", 1)] {
+            let err = parse(source).err().unwrap();
+            assert_eq!(err.code, ErrorCode::ExpectedToken, "{source}");
+            assert_eq!(err.span.unwrap().column, column, "{source}");
+            assert!(err.to_string().contains("end of input"), "{source}");
+        }
+        assert_eq!(eval("[1, 2].len()").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn tokens_borrow_source() {
+        let source = "[\"é\", 12.5].len()";
+        let tokens = Scanner::from(source).scan_tokens().unwrap();
+        let within = source.as_bytes().as_ptr_range();
+
+        for token in &tokens {
+            assert!(within.contains(&token.lexeme.as_ptr()) || token.lexeme.is_empty());
+            assert_eq!(token.lexeme, &source[token.span.start..token.span.end]);
+        }
+        assert!(matches!(
+            tokens[1].literal,
+            Some(Literal::String(Cow::Borrowed("é")))
+        ));
+        // Columns count characters, not bytes.
+        assert_eq!(tokens[3].span, Span::new(7, 11, 1, 7));
+    }
+
+    #[test]
+    fn reject_invalid_assignment_targets() {
+        for source in ["1 += 2", "++1", "(1)--", "[1][0:1] = 2"] {
+            assert!(parse(source).is_err(), "{source} should not parse");
+        }
+
+        // Loop and catch variables are read-only, and say so.
+        for (source, code) in [
+            ("for x in [1] { x = 2 }", ErrorCode::InvalidAssignmentTarget),
+            (
+                "for x in [1] { x %= 2 }",
+                ErrorCode::InvalidAssignmentTarget,
+            ),
+            ("for x in [1] { x++ }", ErrorCode::InvalidIncrementTarget),
+            ("for x in [1] { --x }", ErrorCode::InvalidIncrementTarget),
+            (
+                "try { 1 } catch (e) { e = 1 }",
+                ErrorCode::InvalidAssignmentTarget,
+            ),
+        ] {
+            let err = parse(source).err().unwrap();
+            assert_eq!(err.code, code, "{source}");
+            assert!(
+                err.message.contains("Cannot change the variable"),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn split_doubled_operators_without_a_place() {
+        let cases = [
+            ("1--1", 2.0),
+            ("--1", 1.0),
+            ("2 * --3", 6.0),
+            ("1 - --[3][0]", -1.0),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap(), Value::Number(expected), "{source}");
+            assert_eq!(
+                eval_vm(source).unwrap(),
+                Value::Number(expected),
+                "{source}"
+            );
+        }
+
+        // There is no unary plus, so `1++1` fails just as `1 + +1` does.
+        assert_eq!(
+            parse("1++1").err().unwrap().code,
+            ErrorCode::ExpectedExpression
+        );
+        assert_eq!(
+            parse("(1)++").err().unwrap().code,
+            ErrorCode::InvalidIncrementTarget
+        );
+    }
+
+    #[test]
+    fn arithmetic_and_bitwise_operators() {
+        let cases = [
+            ("7 % 3", 1.0),
+            ("-7 % 3", -1.0),
+            ("7.5 % 2", 1.5),
+            ("6 & 3", 2.0),
+            ("6 | 3", 7.0),
+            ("6 ^ 3", 5.0),
+            ("1 << 4", 16.0),
+            ("256 >> 4", 16.0),
+            ("1 + 2 << 1", 6.0),
+            ("1 | 2 ^ 3 & 6", 1.0),
+            ("2 * 5 % 3", 1.0),
+            ("[7][0] %= 4", 3.0),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap(), Value::Number(expected), "{source}");
+            assert_eq!(
+                eval_vm(source).unwrap(),
+                Value::Number(expected),
+                "{source}"
+            );
+        }
+        assert_eq!(eval("5 & 1 == 1").unwrap(), Value::Bool(true));
+
+        // A remainder by zero is an error, where division gives an infinity.
+        for (source, code) in [
+            ("5 % 0", ErrorCode::RemainderByZero),
+            ("[5][0] %= 0", ErrorCode::RemainderByZero),
+            ("1.5 & 1", ErrorCode::OperandsMustBeIntegers),
+            ("\"a\" % 2", ErrorCode::OperandsMustBeNumbers),
+        ] {
+            assert_eq!(eval(source).err().unwrap().code, code, "{source}");
+            assert_eq!(render(eval(source)), render(eval_vm(source)), "{source}");
+        }
+        assert_eq!(eval("1 / 0").unwrap(), Value::Number(f64::INFINITY));
+
+        assert_eq!(
+            AstPrinter::new().print(parse("1 | 2 & 3 << 4 % 5").unwrap()),
+            "(| 1 (& 2 (<< 3 (% 4 5))))"
+        );
+    }
+
+    #[test]
+    fn conditional_and_coalesce() {
+        let cases = [
+            ("true ? 1 : 2", Value::Number(1.0)),
+            ("false ? 1 : true ? 2 : 3", Value::Number(2.0)),
+            ("nil ?? \"default\"", Value::String("default".into())),
+            ("false ?? 1", Value::Bool(false)),
+            ("nil ?? nil ?? 3", Value::Number(3.0)),
+            // The untaken branch is never evaluated.
+            ("true ? 1 : -\"a\"", Value::Number(1.0)),
+            ("0 ?? -\"a\"", Value::Number(0.0)),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap(), expected, "{source}");
+        }
+
+        assert_eq!(
+            AstPrinter::new().print(parse("1 ?? 2 ? 3 : 4 ? 5 : 6").unwrap()),
+            "(?: (?? 1 2) 3 (?: 4 5 6))"
+        );
+    }
+
+    #[test]
+    fn optional_chaining() {
+        let cases = [
+            ("nil?.len()", "nil"),
+            ("{1: 2}?.len()", "1"),
+            ("[1, 2]?.len()", "2"),
+            ("{}[1]?.len() ?? 0", "0"),
+            // A skipped call does not evaluate its arguments.
+            ("nil?.push([].pop())", "nil"),
+            ("for xs in [[]] { nil?.push(xs.push(1)); xs }", "[[]]"),
+            ("[[1]][0]?.pop()", "1"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap().to_string(), expected, "{source}");
+            assert_eq!(eval_vm(source).unwrap().to_string(), expected, "{source}");
+            let optimized = optimize(&parse(source).unwrap(), 2);
+            let chunk = Compiler::new().compile(&optimized).unwrap();
+            assert_eq!(
+                Vm::new().run(&chunk).unwrap().to_string(),
+                expected,
+                "{source}"
+            );
+        }
+
+        // Only the optional call itself is skipped.
+        for source in ["nil?.len().len()", "1?.len()"] {
+            assert_eq!(eval(source).err().unwrap().code, ErrorCode::UndefinedMethod);
+            assert_eq!(render(eval(source)), render(eval_vm(source)), "{source}");
+        }
+
+        assert_eq!(
+            AstPrinter::new().print(parse("nil?.len()?.push(1)").unwrap()),
+            "(?.push (?.len nil) 1)"
+        );
+    }
+
+    #[test]
+    fn list_operations() {
+        let cases = [
+            ("[1, 2, 3]", "[1, 2, 3]"),
+            ("[1, \"a\", [nil]]", "[1, \"a\", [nil]]"),
+            ("[1, 2, 3][-1]", "3"),
+            ("[1, 2, 3, 4][1:3]", "[2, 3]"),
+            ("[1, 2, 3, 4][:-1]", "[1, 2, 3]"),
+            ("[1, 2, 3, 4][2:]", "[3, 4]"),
+            ("[1, 2, 3][5:]", "[]"),
+            ("[1, 2, 3][0] = 9", "9"),
+            ("[1, 2, 3][1] += 10", "12"),
+            ("[6][0] <<= 2", "24"),
+            ("[1][0]++", "1"),
+            ("++[1][0]", "2"),
+            ("[1, 2].len()", "2"),
+            ("[1, 2].pop()", "2"),
+            ("[1, 2].remove(0)", "1"),
+            ("[1, [2]].contains([2])", "true"),
+            ("[1, 2] == [1, 2]", "true"),
+            ("[1, 2] == [2, 1]", "false"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap().to_string(), expected, "{source}");
+        }
+
+        for source in [
+            "[1][1]",
+            "[1][0.5]",
+            "[].pop()",
+            "[1].push()",
+            "[1].sort()",
+            "1[0]",
+            "[1].insert(-3, 0)",
+        ] {
+            assert!(parse(source).is_ok(), "{source}");
+            assert!(eval(source).is_err(), "{source} should fail");
+        }
+
+        // `insert` returns nil, so the list is checked through another reference to it.
+        let insert = Token::new(TokenType::Identifier, "insert", None, 1);
+        for (index, expected) in [
+            (-1.0, "[1, 5, 2]"),
+            (-2.0, "[5, 1, 2]"),
+            (2.0, "[1, 2, 5]"),
+            (9.0, "[1, 2, 5]"),
+        ] {
+            let list = Value::new_list(vec![Value::Number(1.0), Value::Number(2.0)]);
+            let args = vec![Value::Number(index), Value::Number(5.0)];
+            interpreter::call_method(list.clone(), &insert, args).unwrap();
+            assert_eq!(list.to_string(), expected, "insert({index}, 5)");
+        }
+    }
+
+    #[test]
+    fn self_referential_collections() {
+        let cases = [
+            ("for xs in [[1]] { xs.push(xs); xs }", "[[1, [...]]]"),
+            ("for xs in [[1]] { xs.push(xs); xs == xs }", "[true]"),
+            ("for xs in [[1]] { xs.push(xs); [xs] == [xs] }", "[true]"),
+            ("for xs in [[1]] { xs.push(xs); xs.contains(xs) }", "[true]"),
+            ("for m in [{}] { m[1] = m; m }", "[{1: {...}}]"),
+            ("for m in [{}] { m[1] = [m]; m == m[1][0] }", "[true]"),
+            (
+                "for a in [[]] { for b in [[]] { a.push(a); b.push(b); a == b } }",
+                "[[true]]",
+            ),
+            (
+                "for a in [[1]] { for b in [[2]] { a.push(a); b.push(b); a == b } }",
+                "[[false]]",
+            ),
+            // A list met twice without a cycle is written out both times.
+            ("for xs in [[1]] { [xs, xs] }", "[[[1], [1]]]"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap().to_string(), expected, "{source}");
+            assert_eq!(eval_vm(source).unwrap().to_string(), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn deeply_nested_collections() {
+        // Dropping, writing and comparing these recursively would overflow the native stack.
+        let nest = |depth: usize| {
+            let mut value = Value::Nil;
+            for i in 0..depth {
+                value = match i % 2 {
+                    0 => Value::new_list(vec![value]),
+                    _ => {
+                        let mut map = Map::new();
+                        map.insert(HashKey::Nil, Value::Nil, value);
+                        Value::new_map(map)
+                    }
+                };
+            }
+            value
+        };
+        let (a, b) = (nest(200_000), nest(200_000));
+        assert_eq!(a, b);
+        assert!(a.to_string().starts_with("{nil: [{nil: [{"));
+        assert!(a.to_string().ends_with("}]}]}"));
+        drop((a, b));
+
+        let source = "for xs in [[1]] { for i in 0..20000 { xs.push([xs.pop()]); 0 }; xs.len() }";
+        assert_eq!(eval(source).unwrap().to_string(), "[1]");
+        assert_eq!(eval_vm(source).unwrap().to_string(), "[1]");
+    }
+
+    #[test]
+    fn map_operations() {
+        let cases = [
+            ("{\"a\": 1, \"b\": [2]}", "{\"a\": 1, \"b\": [2]}"),
+            ("{}", "{}"),
+            ("{\"b\": 1, \"a\": 2, \"b\": 3}.keys()", "[\"b\", \"a\"]"),
+            ("{\"b\": 1, \"a\": 2}.values()", "[1, 2]"),
+            ("{1: \"one\", true: \"yes\", nil: 0}[true]", "yes"),
+            ("{0: \"zero\"}[-0]", "zero"),
+            ("{\"a\": 1}[\"missing\"] ?? 5", "5"),
+            ("{\"a\": 1}[\"b\"] = 2", "2"),
+            ("{\"a\": 1}[\"a\"] += 2", "3"),
+            ("{\"a\": 1}.has(\"a\")", "true"),
+            ("{\"a\": 1}.remove(\"a\")", "1"),
+            ("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}", "true"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap().to_string(), expected, "{source}");
+        }
+
+        for source in ["{[1]: 2}", "{\"a\": 1}[{}]", "{}.has([])", "{}[0:1]"] {
+            assert!(parse(source).is_ok(), "{source}");
+            assert!(eval(source).is_err(), "{source} should fail");
+        }
+    }
+
+    #[test]
+    fn range_values() {
+        let cases = [
+            ("0..5", "0..5"),
+            ("1 + 1..=2 * 3", "2..=6"),
+            ("(0..5).len()", "5"),
+            ("(0..=5).len()", "6"),
+            ("(5..0).len()", "0"),
+            ("(0..5).contains(4)", "true"),
+            ("(0..5).contains(5)", "false"),
+            ("(0..=5).contains(5)", "true"),
+            ("0..3 == 0..3", "true"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval(source).unwrap().to_string(), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn for_loops() {
+        let cases = [
+            ("for x in [1, 2, 3] { x * 2 }", "[2, 4, 6]"),
+            ("for k in {\"a\": 1, \"b\": 2} { k }", "[\"a\", \"b\"]"),
+            ("for c in \"hé\" { c }", "[\"h\", \"é\"]"),
+            (
+                "for i in 0..3 { for i in 0..=i { i } }",
+                "[[0], [0, 1], [0, 1, 2]]",
+            ),
+            ("for x in [] { x }", "[]"),
+            ("for x in 0..2 { x; }", "[nil, nil]"),
+            ("for x in [1] {}", "[nil]"),
+            ("for x in [1, 2] { [x].push(x); x + 1 }", "[2, 3]"),
+            (
+                "for xs in [[1]] { for x in xs { x < 3 ? xs.push(x + 1) : 0 } }",
+                "[[nil, nil, 0]]",
+            ),
+            ("[1, 2].iter().next()", "1"),
+            ("(0..0).iter().done()", "true"),
+            (
+                "for it in [[5, 6, 7].iter()] { [it.next(), for x in it { x }, it.done()] }",
+                "[[5, [6, 7], true]]",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(render(eval(source)), expected, "{source}");
+            assert_eq!(render(eval_vm(source)), expected, "{source}");
+        }
+
+        let errors = [
+            ("for x in 5 { x }", ErrorCode::NotIterable),
+            ("nil.iter()", ErrorCode::UndefinedMethod),
+            ("[].iter().next()", ErrorCode::IteratorExhausted),
+            ("[for x in [1] { x }, x]", ErrorCode::UndefinedVariable),
+            ("for in [1] { 1 }", ErrorCode::ExpectedToken),
+            ("for x [1] { x }", ErrorCode::ExpectedToken),
+            ("for x in [1] x", ErrorCode::ExpectedToken),
+            ("for x in [1] { x", ErrorCode::ExpectedToken),
+        ];
+        for (source, code) in errors {
+            assert_eq!(eval(source).err().unwrap().code, code, "{source}");
+            assert_eq!(render(eval(source)), render(eval_vm(source)), "{source}");
+        }
+
+        let err = parse("for item in [1] { itme }").err().unwrap();
+        assert_eq!(err.notes, ["Did you mean 'item'?"]);
+
+        // Loops whose value is unused collect nothing, so long ones fit in a small heap.
+        let sources = [
+            "for n in [0] { for i in 0..100000 { [i] }; n }",
+            "for n in [0] { try { for i in 0..100000 { [i] } } finally { 1 }; n }",
+            "for n in [0] { 1; for i in 0..1000 { for j in 0..100 { [j] } }; n }",
+        ];
+        for source in sources {
+            let expr = parse(source).unwrap();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_heap(Some(4096));
+            assert_eq!(interpreter.interpret(&expr).unwrap().to_string(), "[0]");
+            let chunk = Compiler::new().compile(&expr).unwrap();
+            let mut vm = Vm::new();
+            vm.set_max_heap(Some(4096));
+            assert_eq!(vm.run(&chunk).unwrap().to_string(), "[0]");
+        }
+    }
+
+    #[test]
+    fn reject_loop_control_outside_loops() {
+        for source in [
+            "break",
+            "continue",
+            "break outer",
+            "1 + continue",
+            "break 'a",
+        ] {
+            let err = parse(source).err().unwrap();
+            assert!(err.to_string().contains("outside of a loop"), "{source}");
+        }
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let cases = [
+            ("for x in 0..5 { x > 2 ? break : x }", "[0, 1, 2]"),
+            (
+                "for x in [1, 2, 3, 4] { x == 2 ? continue : x }",
+                "[1, 3, 4]",
+            ),
+            ("for x in 0..3 { [x, x > 0 ? break : x] }", "[[0, 0]]"),
+            (
+                "for x in 0..3 { for y in 0..3 { y > x ? continue : y } }",
+                "[[0], [0, 1], [0, 1, 2]]",
+            ),
+            (
+                "'rows: for x in 0..3 { for y in 0..3 { y > x ? continue 'rows : [x, y] } }",
+                "[[[2, 0], [2, 1], [2, 2]]]",
+            ),
+            (
+                "'rows: for x in 0..3 { [x, for y in [7] { x == 1 ? break 'rows : y }] }",
+                "[[0, [7]]]",
+            ),
+            ("[for x in 0..3 { break }, for x in [1] { x }]", "[[], [1]]"),
+            ("'a: for x in [1] { 'a: for y in [2] { break 'a } }", "[[]]"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(render(eval(source)), expected, "{source}");
+            assert_eq!(render(eval_vm(source)), expected, "{source}");
+            let chunk = Compiler::new().compile(&parse(source).unwrap()).unwrap();
+            assert!(verifier::verify(&chunk, "a.strixc").is_ok(), "{source}");
+        }
+
+        let err = parse("'outer: for x in [1] { break 'outr }").err().unwrap();
+        assert_eq!(err.code, ErrorCode::UndefinedLabel);
+        assert_eq!(err.notes, ["Did you mean 'outer'?"]);
+        assert_eq!(parse("'a: 1").err().unwrap().code, ErrorCode::ExpectedToken);
+    }
+
+    #[test]
+    fn throw_expression() {
+        let cases = [
+            (
+                "throw \"boom\"",
+                "[line 1:1] Runtime error[E0403]: Uncaught error: boom\n    at <script> (<script>:1)",
+            ),
+            (
+                "{\"a\": 1}[\"b\"] ?? throw [\"missing\", \"b\"]",
+                "[line 1:18] Runtime error[E0403]: Uncaught error: [\"missing\", \"b\"]\n    at <script> (<script>:1)",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                eval(source).err().unwrap().to_string(),
+                expected,
+                "{source}"
+            );
+        }
+
+        assert!(eval("1 ?? throw \"unused\"").is_ok());
+
+        // The thrown value stays with the engine, so that hosts can send errors to other
+        // threads, and the error keeps how it displays.
+        fn send_and_sync<T: Send + Sync>() {}
+        send_and_sync::<StrixError>();
+        let err = eval_vm("throw [1, \"a\"]").err().unwrap();
+        assert_eq!(err.value.as_deref(), Some("[1, \"a\"]"));
+        let source = "for x in [[1]] { try { throw x } catch (e) { e.push(2); x } }";
+        assert_eq!(render(eval(source)), "[[1, 2]]");
+        assert_eq!(render(eval_vm(source)), "[[1, 2]]");
+    }
+
+    #[test]
+    fn try_catch_finally() {
+        let cases = [
+            (
+                "try { 1 + nil } catch (e) { [e.message(), e.kind(), e.code(), e.line(), e.column()] }",
+                "[\"The operands must be two numbers or two strings.\", \"type\", \"E0301\", 1, 9]",
+            ),
+            ("try { [][0] } catch (e) { e.kind() }", "runtime"),
+            ("try { [1, throw \"boom\"] } catch (e) { e }", "boom"),
+            ("try { 1 } catch (e) { 2 }", "1"),
+            (
+                "for x in 0..3 { try { x == 1 ? throw x : x } catch (e) { e * 10 } }",
+                "[0, 10, 2]",
+            ),
+            (
+                "try { try { 1 + nil } catch (e) { throw e } } catch (e) { e.code() }",
+                "E0301",
+            ),
+            (
+                "for log in [[]] { try { throw 1 } catch (e) { log.push(e) } finally { log.push(2) }; log }",
+                "[[1, 2]]",
+            ),
+            (
+                "for log in [[]] { try { try { 1 + nil } finally { log.push(1) } } catch (e) { log.push(2) }; log }",
+                "[[1, 2]]",
+            ),
+            (
+                "for log in [[]] { try { try { throw 1 } catch (e) { throw e + 1 } finally { log.push(0) } } catch (e) { log.push(e) }; log }",
+                "[[0, 2]]",
+            ),
+            (
+                "for log in [[]] { for x in 0..3 { try { x == 1 ? break : x } finally { log.push(x) } }; log }",
+                "[[0, 1]]",
+            ),
+            (
+                "for log in [[]] { 'o: for x in 0..2 { try { for y in 0..2 { try { y == 1 ? continue 'o : y } finally { log.push([x, y]) } } } finally { log.push(x) } }; log }",
+                "[[[0, 0], [0, 1], 0, [1, 0], [1, 1], 1]]",
+            ),
+            ("for x in 0..3 { try { throw x } finally { break } }", "[]"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(render(eval(source)), expected, "{source}");
+            assert_eq!(render(eval_vm(source)), expected, "{source}");
+            let chunk = Compiler::new().compile(&parse(source).unwrap()).unwrap();
+            assert!(verifier::verify(&chunk, "a.strixc").is_ok(), "{source}");
+        }
+
+        let errors = [
+            ("try { throw 1 } finally { 2 }", ErrorCode::UncaughtThrow),
+            (
+                "try { 1 + nil } catch (e) { e.line(1) }",
+                ErrorCode::WrongArity,
+            ),
+            ("try { 1 } catch e { 2 }", ErrorCode::ExpectedToken),
+            ("try { 1 }", ErrorCode::ExpectedToken),
+            (
+                "[try { 1 } catch (e) { 2 }, e]",
+                ErrorCode::UndefinedVariable,
+            ),
+        ];
+        for (source, code) in errors {
+            assert_eq!(eval(source).err().unwrap().code, code, "{source}");
+            assert_eq!(render(eval(source)), render(eval_vm(source)), "{source}");
+        }
+
+        // Exceeding a limit stops the script even inside a `try`, without running a cleanup
+        // that could replace the error.
+        let sources = [
+            ("try { [1, 2, 3] } catch (e) { 0 }", 64),
+            (
+                "for x in [1] { try { for y in 0..100000 { [y, y, y, y] } } finally { break } }",
+                5000,
+            ),
+            ("try { [1, 2, 3] } finally { throw 1 }", 64),
+        ];
+        for (source, max_heap) in sources {
+            let expr = parse(source).unwrap();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_heap(Some(max_heap));
+            let err = interpreter.interpret(&expr).err().unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory, "{source}");
+            let mut vm = Vm::new();
+            vm.set_max_heap(Some(max_heap));
+            let err = vm
+                .run(&Compiler::new().compile(&expr).unwrap())
+                .err()
+                .unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory, "{source}");
+        }
+
+        // Each cleanup is compiled once, however many trys a `break` leaves or nest in
+        // cleanups.
+        let mut inner = String::from("x == 1 ? break : x");
+        for level in 1..=14 {
+            inner = format!(
+                "try {{ {inner} }} finally {{ try {{ log.push({level}) }} finally {{ log.push(-{level}) }} }}"
+            );
+        }
+        let source = format!("for log in [[]] {{ for x in 0..3 {{ {inner} }}; log.len() }}");
+        assert_eq!(render(eval(&source)), "[56]");
+        assert_eq!(render(eval_vm(&source)), "[56]");
+        let chunk = Compiler::new().compile(&parse(&source).unwrap()).unwrap();
+        assert!(chunk.code.len() < 5000, "{}", chunk.code.len());
+        assert!(verifier::verify(&chunk, "a.strixc").is_ok());
+    }
+
+    #[test]
+    fn runtime_errors_carry_stack_trace() {
+        let expr = parse("[1].push(\n[].pop())").unwrap();
+        let err = Interpreter::with_file("main.strix".to_string())
+            .interpret(&expr)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "[line 2:4] Runtime error[E0401]: Cannot pop from an empty list.\n    \
+             at list.pop (main.strix:2)\n    \
+             at <script> (main.strix:2)"
+        );
+    }
+
+    #[test]
+    fn suggest_close_names() {
+        assert_eq!(suggest::closest("ture", ["true", "false"]), Some("true"));
+        assert_eq!(suggest::closest("fasle", ["true", "false"]), Some("false"));
+        assert_eq!(suggest::closest("psh", ["push", "pop"]), Some("push"));
+        assert_eq!(suggest::closest("sort", ["push", "pop"]), None);
+        assert_eq!(suggest::closest("pushAll", ["push", "pop"]), Some("push"));
+        assert_eq!(suggest::closest("fuzzy", ["fun", "for"]), None);
+
+        // Far or short names are not worth suggesting, nor are short names they start with.
+        assert_eq!(suggest::closest("lenght", ["len", "pop"]), None);
+        assert_eq!(suggest::closest("x", ["y", "xs"]), None);
+        assert_eq!(suggest::closest("ab", ["ba", "xy"]), None);
+        assert_eq!(suggest::closest("count", ["contains"]), None);
+
+        let err = parse("retrun").err().unwrap();
+        assert_eq!(err.notes, ["Did you mean 'return'?"]);
+        for name in ["function", "fucntion"] {
+            assert!(parse(name).err().unwrap().notes.is_empty(), "{name}");
+        }
+
+        let err = parse("nill ?? 1").err().unwrap();
+        assert_eq!(err.code, ErrorCode::UndefinedVariable);
+        assert_eq!(err.notes, ["Did you mean 'nil'?"]);
+
+        let err = eval("{}.kyes()").err().unwrap();
+        assert_eq!(err.notes, ["Did you mean 'keys'?"]);
+    }
+
+    #[test]
+    fn localized_diagnostics() {
+        assert_eq!(Locale::parse("pt_BR.UTF-8"), Some(Locale::PtBr));
+        assert_eq!(Locale::parse("en-US"), Some(Locale::En));
+        assert_eq!(Locale::parse("C"), Some(Locale::En));
+        assert_eq!(Locale::parse("fr_FR"), None);
+
+        // Errors keep the language they were created in, wherever they are rendered.
+        let err = messages::with_locale(Locale::PtBr, || eval("[].pop()").err().unwrap());
+        assert_eq!(messages::locale(), Locale::En);
+        assert_eq!(
+            err.to_string(),
+            "[linha 1:4] Erro de execução [E0401]: Não é possível remover de uma lista vazia.\n    \
+             em list.pop (<script>:1)\n    \
+             em <script> (<script>:1)"
+        );
+        assert_eq!(err.kind, ErrorKind::Runtime);
+    }
+
+    #[test]
+    fn evaluate_through_the_embedding_api() {
+        let mut strix = Strix::new();
+        for engine in [Engine::Tree, Engine::Vm] {
+            strix.set_engine(engine);
+            assert_eq!(render(strix.eval("[1, 2].len() + 1")), "3");
+            let err = strix.eval("[].pop()").err().unwrap();
+            assert_eq!(err.code, ErrorCode::EmptyList);
+            assert_eq!(
+                err.to_string().lines().next(),
+                Some("[line 1:4] Runtime error[E0401]: Cannot pop from an empty list.")
+            );
+        }
+
+        // Hosts can see what the garbage collector did during the last run.
+        let cycles = "for i in 0..3 { for l in [[]] { l.push(l); nil }; i }";
+        let mut stats = vec![];
+        for engine in [Engine::Tree, Engine::Vm] {
+            strix.set_engine(engine);
+            strix.set_gc_stress(true);
+            assert_eq!(render(strix.eval(cycles)), "[0, 1, 2]");
+            stats.push(strix.gc_stats());
+            assert_eq!(render(strix.eval("[[]].len()")), "1");
+            assert_eq!(strix.gc_stats().allocated, 2);
+        }
+        assert!(stats[0].collected >= 2, "{}", stats[0]);
+        assert_eq!(stats[0], stats[1]);
+        strix.set_gc_stress(false);
+
+        // Each instance keeps its own language.
+        let mut pt_br = Strix::new();
+        pt_br.set_locale(Locale::PtBr);
+        let err = pt_br.eval("[].pop()").err().unwrap();
+        assert!(
+            err.to_string().starts_with("[linha 1:4] Erro de execução"),
+            "{err}"
+        );
+        let err = strix.eval("[].pop()").err().unwrap();
+        assert!(
+            err.to_string().starts_with("[line 1:4] Runtime error"),
+            "{err}"
+        );
+
+        let dir = env::temp_dir();
+        let source = dir.join(format!("strix-eval-{}.strix", process::id()));
+        let compiled = source.with_extension("strixc");
+        let chunk = Compiler::new()
+            .compile(&parse("[1, 2].len() * 3").unwrap())
+            .unwrap();
+        fs::write(&source, "{\"a\": 1}[\"a\"] * 2").unwrap();
+        fs::write(&compiled, bytecode::write(&chunk)).unwrap();
+        let source_result = strix.eval_file(source.to_str().unwrap());
+        let compiled_result = strix.eval_file(compiled.to_str().unwrap());
+        let _ = fs::remove_file(&source);
+        let _ = fs::remove_file(&compiled);
+        assert_eq!(render(source_result), "2");
+        assert_eq!(render(compiled_result), "6");
+
+        let err = strix.eval_file(source.to_str().unwrap()).err().unwrap();
+        assert_eq!(err.code, ErrorCode::UnreadableFile);
+    }
+
+    #[test]
+    fn render_errors_as_json() {
+        let err = parse("(\"\\t\" + [2").err().unwrap();
+        assert_eq!(
+            err.render_json(Some("main.strix")),
+            "{\"kind\":\"syntax\",\"code\":\"E0101\",\
+             \"message\":\"Expect ']' after list elements.\",\"file\":\"main.strix\",\
+             \"line\":1,\"column\":11,\"span\":{\"start\":10,\"end\":10,\"line\":1,\"column\":11},\
+             \"labels\":[{\"span\":{\"start\":8,\"end\":9,\"line\":1,\"column\":9},\
+             \"message\":\"to match this '['\"}],\"notes\":[],\"stack\":[]}"
+        );
+
+        let err = eval("throw \"a\tb\"").err().unwrap();
+        assert_eq!(
+            err.render_json(None),
+            "{\"kind\":\"runtime\",\"code\":\"E0403\",\
+             \"message\":\"Uncaught error: a\\tb\",\"file\":null,\
+             \"line\":1,\"column\":1,\"span\":{\"start\":0,\"end\":5,\"line\":1,\"column\":1},\
+             \"labels\":[],\"notes\":[],\
+             \"stack\":[{\"function\":\"<script>\",\"file\":\"<script>\",\"line\":1}]}"
+        );
+    }
+
+    #[test]
+    fn explain_examples_match_their_codes() {
+        for code in ErrorCode::ALL {
+            assert_eq!(
+                ErrorCode::parse(&code.to_string().to_lowercase()),
+                Some(code)
+            );
+
+            if let Some((wrong, right)) = Explanation::of(code).example {
+                assert_eq!(eval(wrong).err().map(|err| err.code), Some(code), "{wrong}");
+                assert!(eval(right).is_ok(), "{right}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors_carry_kind_code_and_span() {
+        let scanner = Scanner::from("1 +\n  @");
+        let err = scanner.scan_tokens().err().unwrap();
+        assert_eq!(err.kind, ErrorKind::Lexical);
+        assert_eq!(err.code, ErrorCode::UnexpectedCharacter);
+        assert_eq!(err.span, Some(Span::new(6, 7, 2, 3)));
+
+        let err = parse("(1 + [2\n, 3").err().unwrap();
+        assert_eq!(err.code, ErrorCode::ExpectedToken);
+        assert_eq!(
+            err.to_string(),
+            "[line 2:4] Syntax error[E0101]: Expect ']' after list elements.\n    \
+             [line 1:6] to match this '['"
+        );
+
+        let err = eval("{[1]: 2}").err().unwrap();
+        assert_eq!(err.kind, ErrorKind::Type);
+        assert_eq!(err.code, ErrorCode::UnhashableKey);
+        assert_eq!(err.notes.len(), 1);
+    }
+
+    #[test]
+    fn nesting_and_call_depth_limits() {
+        let parse_nested = |source: &str| {
+            let mut parser = Parser::new(Scanner::from(source));
+            parser.set_max_depth(32);
+            parser
+                .parse()
+                .map(|expr| Interpreter::new().interpret(&expr))
+        };
+
+        let within_limit = [
+            format!("{}1{}", "(".repeat(30), ")".repeat(30)),
+            format!("{}1{}", "[".repeat(30), "]".repeat(30)),
+            format!("{}true", "!".repeat(30)),
+            format!("1{}", " + 1".repeat(30)),
+        ];
+        for source in within_limit {
+            assert!(parse_nested(&source).unwrap().is_ok(), "{source}");
+        }
+
+        let too_deep = [
+            format!("{}1{}", "(".repeat(1000), ")".repeat(1000)),
+            format!("{}true", "!".repeat(1000)),
+            format!("1{}", " + 1".repeat(1000)),
+            format!("[1]{}", "[0]".repeat(1000)),
+        ];
+        for source in too_deep {
+            let err = parse_nested(&source).err().unwrap();
+            assert!(err.to_string().contains("nested too deeply"));
+        }
+
+        // Without script functions, at most the script and one method are active, however
+        // deeply method calls nest in arguments.
+        let source = format!("{}1{}", "[".repeat(10), "].len()".repeat(10));
+        let expr = parse(&source).unwrap();
+        let chunk = Compiler::new().compile(&expr).unwrap();
+        for max_call_depth in [2, interpreter::DEFAULT_MAX_CALL_DEPTH] {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_call_depth(max_call_depth);
+            assert_eq!(interpreter.interpret(&expr).unwrap(), Value::Number(1.0));
+            let mut vm = Vm::new();
+            vm.set_max_call_depth(max_call_depth);
+            assert_eq!(vm.run(&chunk).unwrap(), Value::Number(1.0));
+        }
+
+        let expr = parse("[1].len()").unwrap();
+        let chunk = Compiler::new().compile(&expr).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(1);
+        let err = interpreter.interpret(&expr).err().unwrap();
+        assert!(err.to_string().contains("Stack overflow"));
+        let mut vm = Vm::new();
+        vm.set_max_call_depth(1);
+        assert_eq!(vm.run(&chunk).err().unwrap().code, ErrorCode::StackOverflow);
+
+        // The overflow is caught like any runtime error.
+        let expr = parse("try { [1].len() } catch (e) { e }").unwrap();
+        let chunk = Compiler::new().compile(&expr).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(1);
+        let caught = interpreter.interpret(&expr).unwrap();
+        assert!(caught.to_string().starts_with("<error E0501"), "{caught}");
+        let mut vm = Vm::new();
+        vm.set_max_call_depth(1);
+        assert_eq!(vm.run(&chunk).unwrap().to_string(), caught.to_string());
+    }
+
+    #[test]
+    fn engines_agree() {
+        let sources = [
+            "1 + 2 * 3 - 4 / 8",
+            "\"a\" + \"b\" == \"ab\"",
+            "!nil != (1 < 2)",
+            "false ? 1 : nil ?? \"x\"",
+            "0 ?? -\"a\"",
+            "[1, [2, 3], {\"k\": 4}][1][0:]",
+            "[1, 2, 3][:-1]",
+            "{1: \"a\", true: [nil]}[true]",
+            "(1..=4).len()",
+            "[1, 2][0] += 5",
+            "[1, 2][1] <<= 2",
+            "[3][0]++",
+            "--[3][0]",
+            "[].pop()",
+            "[1, 2].push(3, 4)",
+            "{\"a\": 1}.keys()",
+            "[1][5]",
+            "[1][0] /= \"a\"",
+            "{[1]: 2}",
+            "1 +\n  -\"a\"",
+            "nil[0]",
+            "\"s\"[0:\"e\"]",
+            "(1..2).nope()",
+            "[[1, 2].pop(), [].len()].pop(1)",
+            "nil ?? throw \"missing\"",
+            "throw [1, {\"a\": 2}]",
+        ];
+
+        for source in sources {
+            assert_eq!(render(eval(source)), render(eval_vm(source)), "{source}");
+        }
+    }
+
+    #[test]
+    fn bytecode_files_round_trip() {
+        let expr = parse("[1.5, \"é\"].push(\n  {nil: 2}[nil] + -0)").unwrap();
+        let chunk = Compiler::new().compile(&expr).unwrap();
+        let bytes = bytecode::write(&chunk);
+
+        let loaded = bytecode::read(&bytes, "a.strixc").unwrap();
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.spans, chunk.spans);
+        assert_eq!(
+            Vm::new().run(&loaded).unwrap(),
+            Vm::new().run(&chunk).unwrap()
+        );
+
+        let mut flipped = bytes.clone();
+        flipped[12] ^= 1;
+        let mut newer = bytes.clone();
+        newer[5] += 1;
+        let cases = [
+            (b"[1, 2]".to_vec(), ErrorCode::NotBytecode),
+            (newer, ErrorCode::BytecodeVersion),
+            (flipped, ErrorCode::CorruptBytecode),
+            (
+                bytes[..bytes.len() - 1].to_vec(),
+                ErrorCode::CorruptBytecode,
+            ),
+            (bytes[..6].to_vec(), ErrorCode::CorruptBytecode),
+        ];
+        for (bytes, code) in cases {
+            let err = bytecode::read(&bytes, "a.strixc").err().unwrap();
+            assert_eq!(err.code, code, "{err}");
+        }
+    }
+
+    #[test]
+    fn verify_untrusted_bytecode() {
+        let chunk = |code: &[u8], constants: Vec<Value>| {
+            let mut chunk = Chunk::new();
+            for &byte in code {
+                chunk.write(byte, Span::new(0, 0, 1, 1));
+            }
+            chunk.constants = constants;
+            chunk
+        };
+        let (constant, nil, pop, ret) = (
+            OpCode::Constant as u8,
+            OpCode::Nil as u8,
+            OpCode::Pop as u8,
+            OpCode::Return as u8,
+        );
+
+        let invalid = [
+            chunk(&[200], vec![]),
+            chunk(&[nil], vec![]),
+            chunk(&[constant, 0], vec![]),
+            chunk(&[constant, 0, 1, ret], vec![Value::Nil]),
+            chunk(&[pop, ret], vec![]),
+            chunk(&[nil, OpCode::Jump as u8, 0, 9, ret], vec![]),
+            chunk(&[nil, OpCode::Invoke as u8, 0, 0, 0, ret], vec![Value::Nil]),
+            // The jump skips the second nil, so the return is reached at two depths.
+            chunk(&[nil, OpCode::JumpIfFalse as u8, 0, 1, nil, ret], vec![]),
+            chunk(&[OpCode::PopLocal as u8, nil, ret], vec![]),
+            chunk(&[OpCode::GetLocal as u8, 0, 0, ret], vec![]),
+            chunk(&[nil, OpCode::Loop as u8, 0, 9, ret], vec![]),
+            // The loop leaves a local behind each time around.
+            chunk(
+                &[nil, OpCode::PushLocal as u8, OpCode::Loop as u8, 0, 4],
+                vec![],
+            ),
+        ];
+        for chunk in invalid {
+            let err = verifier::verify(&chunk, "a.strixc").err().unwrap();
+            assert_eq!(err.code, ErrorCode::InvalidBytecode, "{:?}", chunk.code);
+        }
+
+        // Whatever a corrupted chunk contains, it is either rejected or runs without panicking.
+        let expr = parse("[1, {2: 3}][1][2] += true ? [].len() : nil ?? 4").unwrap();
+        let compiled = Compiler::new().compile(&expr).unwrap();
+        assert!(verifier::verify(&compiled, "a.strixc").is_ok());
+
+        let mut seed: u64 = 0x5eed;
+        for _ in 0..2000 {
+            let mut chunk = compiled.clone();
+            for _ in 0..3 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let offset = (seed >> 33) as usize % chunk.code.len();
+                chunk.code[offset] = (seed >> 17) as u8;
+            }
+            if verifier::verify(&chunk, "a.strixc").is_ok() {
+                let _ = Vm::new().run(&chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn disassemble_chunks() {
+        let expr = parse("[\"a\"][0] += nil ??\n  {1: 2}.len()").unwrap();
+        let chunk = Compiler::new().compile(&expr).unwrap();
+
+        assert_eq!(
+            disasm::disassemble(&chunk, "<script>"),
+            "== <script> ==\n\
+             0000    1 Constant           0 \"a\"\n\
+             0003    | List               1\n\
+             0006    | Constant           1 0\n\
+             0009    | Nil\n\
+             0010    | JumpIfNotNil      14 -> 0027\n\
+             0013    | Pop\n\
+             0014    | Constant           2 1\n\
+             0017    | Constant           3 2\n\
+             0020    2 Map                1\n\
+             0023    | Invoke             4 \"len\" (0 args)\n\
+             0027    1 SetIndex           1 PlusEqual\n\
+             0029    | Return\n"
+        );
+    }
+
+    #[test]
+    fn fold_constants() {
+        let cases = [
+            ("-123 * (45.67)", "-5617.41"),
+            ("\"a\" + \"b\" == \"ab\"", "true"),
+            ("!(1 < 2) != !nil", "true"),
+            ("[1 + 2, ((3))][0:(4 - 3)]", "(slice (list 3 3) 0 1)"),
+            ("true ? 2 * 3 : -\"a\"", "(?: true 6 (- a))"),
+            ("(\"a\" + 1) - 2", "(- (+ a 1) 2)"),
+        ];
+
+        for (source, expected) in cases {
+            let optimized = optimize(&parse(source).unwrap(), 1);
+            assert_eq!(AstPrinter::new().print(optimized), expected, "{source}");
+        }
+
+        // Operations that fail are kept, and fail at the same place as before.
+        let source = "[1][0] +\n (\"a\" - 1)";
+        let optimized = optimize(&parse(source).unwrap(), 1);
+        let err = Interpreter::new().interpret(&optimized).err().unwrap();
+        assert_eq!(err.to_string(), eval(source).err().unwrap().to_string());
+        assert_eq!(err.span, Some(Span::new(15, 16, 2, 7)));
+    }
+
+    #[test]
+    fn optimization_levels_agree() {
+        let sources = [
+            "-123 * (45.67)",
+            "true ? 1 : -\"a\"",
+            "!nil ? [1, 2 + 3] : throw \"no\"",
+            "(1 < 2) == false ? 1 : nil ?? \"x\" + \"y\"",
+            "0 ?? -\"a\"",
+            "nil ?? throw \"missing\"",
+            "[1, 2][true ? 0 : 1] += (\"a\" ?? 1)",
+            "(\"a\" - 1) ?? 2",
+            "[[1].pop(), (nil ?? {1: 2})[1]].len()",
+            "false ? [].pop() : [].pop()",
+            "for x in 0..4 { x > 1 ? break : x; 5 }",
+            "for x in [1, 2] { continue; x }",
+            "for x in [1, 2] { 1; x; \"s\"; [x] }",
+            "for x in [1] { throw x; 2 }",
+            "for x in [1] { [x, break, x] }",
+            "for x in [1] { (break) + 1 }",
+            "for x in [1] { continue ? 1 : 2 }",
+            "for x in [1] { break ?? 2 }",
+            "for x in 0..3 { [] ? x : -\"a\" }",
+            "for x in 0..3 { {1: x} ?? throw \"nil\" }",
+            "for x in 0..2 { [x].pop(); x }",
+            "for x in [1] { {[1]: 2}; x }",
+            "'o: for x in 0..3 { for y in 0..3 { y > x ? continue 'o : 0; [x, y] } }",
+            "try { throw 1; 2 } catch (e) { e }",
+            "try { [1][5]; 0 } catch (e) { e.code() } finally { 1 }",
+            "for x in [1, 2] { try { continue; 1 } finally { x } }",
+        ];
+
+        for source in sources {
+            let expr = parse(source).unwrap();
+            let expected = render(eval(source));
+            for level in 0..=2 {
+                let optimized = optimize(&expr, level);
+                let chunk = Compiler::new().compile(&optimized).unwrap();
+                assert!(
+                    verifier::verify(&chunk, "a.strixc").is_ok(),
+                    "-O{level} {source}"
+                );
+                assert_eq!(
+                    render(Vm::new().run(&chunk)),
+                    expected,
+                    "-O{level} {source}"
+                );
+                let tree = Interpreter::new().interpret(&optimized);
+                assert_eq!(render(tree), expected, "-O{level} {source}");
+            }
+        }
+
+        let eliminated = [
+            ("false ? 1 : [2 * 3]", "(list 6)"),
+            ("!nil ? 1 : throw \"no\"", "1"),
+            ("nil ?? (1 ?? 2)", "1"),
+            ("\"s\" ?? throw 1", "s"),
+            (
+                "[nil ?? 1, [] ?? 2, [[].pop()] ?? 3]",
+                "(list 1 (list) (?? (list (.pop (list))) 3))",
+            ),
+            ("for x in [1] { {1: x} ? 1 : 2 }", "(for x (list 1) 1)"),
+            (
+                "for x in [1] { x; break; x + 1 }",
+                "(for x (list 1) (break))",
+            ),
+            (
+                "for x in [1] { (continue) + 1 }",
+                "(for x (list 1) (continue))",
+            ),
+            (
+                "for x in [1] { 1; [].pop(); \"s\"; x }",
+                "(for x (list 1) (block (.pop (list)) x))",
+            ),
+        ];
+        for (source, expected) in eliminated {
+            let optimized = optimize(&parse(source).unwrap(), 2);
+            assert_eq!(AstPrinter::new().print(optimized), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn intern_strings() {
+        let mut strings = Interner::new();
+        let a = strings.intern("len");
+        assert_eq!(strings.intern("len"), a);
+        assert_ne!(strings.intern("pop"), a);
+        assert_eq!(&**strings.resolve(a), "len");
+
+        // Equal string literals share one allocation on both engines.
+        let source = "[\"abc\", \"abc\", \"abc\" + \"\"]";
+        let chunk = Compiler::new().compile(&parse(source).unwrap()).unwrap();
+        assert_eq!(chunk.constants.len(), 2);
+
+        for list in [eval(source).unwrap(), eval_vm(source).unwrap()] {
+            let Value::List(list) = list else { panic!() };
+            let list = list.borrow();
+            let [Value::String(a), Value::String(b), Value::String(c)] = &list[..] else {
+                panic!()
+            };
+            assert!(Rc::ptr_eq(a, b));
+            assert!(!Rc::ptr_eq(a, c));
+        }
+
+        // A method name and a string literal with the same text are one constant.
+        let source = "[\"iter\", \"iter\".iter()]";
+        let chunk = Compiler::new().compile(&parse(source).unwrap()).unwrap();
+        assert_eq!(chunk.constants, [Value::String("iter".into())]);
+
+        // Duplicate constants in a loaded file share one allocation.
+        let mut chunk = Chunk::new();
+        for byte in [OpCode::Constant as u8, 0, 0, OpCode::Pop as u8] {
+            chunk.write(byte, Span::new(0, 0, 1, 1));
+        }
+        for byte in [OpCode::Constant as u8, 0, 1, OpCode::Return as u8] {
+            chunk.write(byte, Span::new(0, 0, 1, 1));
+        }
+        chunk.constants = vec![Value::String("abc".into()), Value::String("abc".into())];
+        let loaded = bytecode::read(&bytecode::write(&chunk), "a.strixc").unwrap();
+        let [Value::String(a), Value::String(b)] = &loaded.constants[..] else {
+            panic!()
+        };
+        assert!(Rc::ptr_eq(a, b));
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics() {
+        let fragments = [
+            "(", ")", "[", "]", "{", "}", ",", ".", "..", "..=", ":", "?", "??", "+", "-", "*",
+            "/", "!", "=", "==", "<", "<<=", "+=", "++", "--", "1", "0.5", "\"s", "\"s\"", "nil",
+            "true", "x", "é", "\u{0}", "\n", "throw", "break", "len", "pop", "push", "keys", "%",
+            "&", "<<", "@", "?.", "//",
+        ];
+
+        let mut sources = vec![String::new(), "\u{FEFF}".to_string(), "ção".to_string()];
+        let mut seed: u64 = 0x5eed;
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..(seed % 24) {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                source.push_str(fragments[(seed >> 33) as usize % fragments.len()]);
+                source.push(' ');
+            }
+            sources.push(source);
+        }
+
+        for source in sources {
+            let scanner = Scanner::from(&source);
+            let Ok(tokens) = scanner.scan_tokens() else {
+                continue;
+            };
+
+            let mut parser = Parser::from(tokens);
+            parser.set_max_depth(32);
+            if let Ok(expr) = parser.parse() {
+                let _ = Interpreter::new().interpret(&expr);
+                if let Ok(chunk) = Compiler::new().compile(&expr) {
+                    let _ = Vm::new().run(&chunk);
+                }
+            }
+        }
+
+        let mut parser = Parser::from(vec![]);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn collect_garbage() {
+        // A list that contains itself is never freed by reference counting alone.
+        let mut heap = Heap::new();
+        let list = Value::new_list(vec![]);
+        let Value::List(cell) = &list else {
+            unreachable!()
+        };
+        let weak = Rc::downgrade(cell);
+        cell.borrow_mut().push(list.clone());
+        heap.track(&list);
+
+        heap.collect(&[]);
+        assert!(weak.upgrade().is_some());
+
+        drop(list);
+        assert!(weak.upgrade().is_some());
+        heap.collect(&[]);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(heap.stats().collected, 1);
+
+        // Nor is one that reaches itself through an iterator or a thrown value.
+        let err = StrixError::new(ErrorCode::UncaughtThrow, String::new());
+        for thrown in [false, true] {
+            let mut heap = Heap::new();
+            let list = Value::new_list(vec![]);
+            let Value::List(cell) = &list else {
+                unreachable!()
+            };
+            let weak = Rc::downgrade(cell);
+            let wrapped = match thrown {
+                false => Value::Iterator(Iter::new(&list).unwrap()),
+                true => Value::Error(Caught::thrown(err.clone(), list.clone())),
+            };
+            cell.borrow_mut().push(wrapped.clone());
+            heap.track(&list);
+
+            // Holding the iterator or error keeps the list alive.
+            drop(list);
+            heap.collect(&[]);
+            assert!(weak.upgrade().is_some());
+            drop(wrapped);
+            heap.collect(&[]);
+            assert!(weak.upgrade().is_none());
+        }
+
+        let sources = [
+            "[[1, 2], {\"a\": [3]}, [4, 5][0:1]]",
+            "{\"k\": [1]}.values()[0].len()",
+            "[[], [[]]][1]",
+            "for l in [[]] { l.push(l.iter()); l.len() }",
+        ];
+        for source in sources {
+            let expr = parse(source).unwrap();
+            let chunk = Compiler::new().compile(&expr).unwrap();
+            let expected = eval(source).unwrap();
+
+            let mut interpreter = Interpreter::new();
+            interpreter.set_gc_stress(true);
+            assert_eq!(interpreter.interpret(&expr).unwrap(), expected);
+            let mut vm = Vm::new();
+            vm.set_gc_stress(true);
+            assert_eq!(vm.run(&chunk).unwrap(), expected);
+            assert_eq!(vm.gc_stats(), interpreter.gc_stats(), "{source}");
+
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_heap(Some(64));
+            let err = interpreter.interpret(&expr).err().unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory);
+            let mut vm = Vm::new();
+            vm.set_max_heap(Some(64));
+            let err = vm.run(&chunk).err().unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory);
+        }
+
+        // Growing a list or map counts against the limit as creating one does.
+        let sources = [
+            "for l in [[]] { for x in 0..100000 { l.push(x); nil }; l.len() }",
+            "for m in [{}] { for x in 0..100000 { m[x] = x; nil }; m.len() }",
+            "for x in 0..100000 { x }",
+        ];
+        for source in sources {
+            let expr = parse(source).unwrap();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_heap(Some(10000));
+            let err = interpreter.interpret(&expr).err().unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory, "{source}");
+            assert!(interpreter.gc_stats().peak_bytes > 10000, "{source}");
+
+            let mut vm = Vm::new();
+            vm.set_max_heap(Some(10000));
+            let err = vm
+                .run(&Compiler::new().compile(&expr).unwrap())
+                .err()
+                .unwrap();
+            assert_eq!(err.code, ErrorCode::OutOfMemory, "{source}");
+            assert_eq!(vm.gc_stats(), interpreter.gc_stats(), "{source}");
+        }
+    }
 }
@@ -0,0 +1,457 @@
+use std::{cell::Cell, env, fmt::Display};
+
+use crate::err::ErrorKind;
+
+/// A language user-facing messages can be shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    PtBr,
+}
+
+impl Locale {
+    /// Reads a language tag such as `pt-BR`, or a POSIX locale such as `pt_BR.UTF-8`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag.split(['_', '-', '.']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Self::En),
+            "pt" => Some(Self::PtBr),
+            _ => None,
+        }
+    }
+
+    /// The locale named by `LC_ALL`, `LC_MESSAGES` or `LANG`, in that order of precedence.
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+    }
+}
+
+thread_local! {
+    static LOCALE: Cell<Locale> = const { Cell::new(Locale::En) };
+}
+
+/// Runs `f` with the messages created on this thread in `locale`, as each `Strix` instance
+/// does with its own, then goes back to the language before.
+pub fn with_locale<T>(locale: Locale, f: impl FnOnce() -> T) -> T {
+    /// Restores the language before, even if `f` panics.
+    struct Restore(Locale);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LOCALE.with(|current| current.set(self.0));
+        }
+    }
+
+    let _restore = Restore(LOCALE.with(|current| current.replace(locale)));
+    f()
+}
+
+/// The language of the messages created on this thread.
+pub fn locale() -> Locale {
+    LOCALE.with(Cell::get)
+}
+
+/// Every user-facing message. Error messages are grouped under the code they are reported
+/// with; `{}` placeholders are filled in order by `tr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    // E0001, E0002.
+    UnexpectedCharacter,
+    UnterminatedString,
+    CloseString,
+
+    // E0100.
+    ExpectedExpression,
+    ExpectIndex,
+
+    // E0101.
+    ExpectColonAfterThen,
+    ExpectMethodName,
+    ExpectParenAfterMethod,
+    ExpectBracketAfterSlice,
+    ExpectBracketAfterIndex,
+    ExpectParenAfterArguments,
+    ExpectBracketAfterElements,
+    ExpectColonAfterKey,
+    ExpectBraceAfterEntries,
+    ExpectParenAfterExpression,
+    ExpectEndOfInput,
+    ExpectLoopVariable,
+    ExpectInAfterLoopVariable,
+    ExpectBraceBeforeBlock,
+    ExpectBraceAfterBlock,
+    ExpectColonAfterLabel,
+    ExpectLoopAfterLabel,
+    ExpectParenAfterCatch,
+    ExpectCatchVariable,
+    ExpectParenAfterCatchVariable,
+    ExpectCatchOrFinally,
+    MatchDelimiter,
+
+    // E0102, E0103.
+    InvalidAssignmentTarget,
+    AssignableTargets,
+    InvalidIncrementTarget,
+    IncrementableTargets,
+    ReadOnlyVariable,
+    VariablesAreReadOnly,
+
+    // E0200 to E0202.
+    LoopControlOutsideLoop,
+    UndefinedVariable,
+    DidYouMean,
+    UndefinedLabel,
+
+    // E0300 to E0311.
+    OperandsMustBeNumbers,
+    InvalidAddOperands,
+    OperandMustBeNumber,
+    OperandsMustBeIntegers,
+    NotIndexable,
+    NotSliceable,
+    ListIndexNotInteger,
+    SliceBoundsNotIntegers,
+    UnhashableKey,
+    HashableKeys,
+    InvalidRangeBounds,
+    NoMethods,
+    UndefinedMethod,
+    WrongArity,
+    NotIterable,
+    IterableValues,
+
+    // E0400 to E0405.
+    IndexOutOfBounds,
+    EmptyList,
+    UnsupportedBinaryOperator,
+    UnsupportedUnaryOperator,
+    UnsupportedAssignmentOperator,
+    UncaughtThrow,
+    IteratorExhausted,
+    CheckDone,
+    RemainderByZero,
+
+    // E0500 to E0503.
+    NestingTooDeep,
+    RaiseMaxNesting,
+    StackOverflow,
+    RaiseMaxCallDepth,
+    TooManyConstants,
+    JumpTooFar,
+    TooManyElements,
+    TooManyArguments,
+    TooManyLocals,
+    OutOfMemory,
+    RaiseMaxHeap,
+
+    // E0600 to E0606.
+    UnreadableFile,
+    InvalidEncoding,
+    UnwritableFile,
+    NotBytecode,
+    BytecodeVersion,
+    TruncatedBytecode,
+    ChecksumMismatch,
+    InvalidBytecode,
+    BadOpcode,
+    BadOperand,
+    BadJump,
+    StackUnderflow,
+    StackMismatch,
+    RunsPastEnd,
+
+    // Diagnostic rendering.
+    Location,
+    KindError,
+    Help,
+    Frame,
+    MoreFrames,
+
+    // REPL.
+    UnknownCommand,
+    ClearScreenFailed,
+    Bye,
+}
+
+impl Msg {
+    fn en(self) -> &'static str {
+        match self {
+            Self::UnexpectedCharacter => "Unexpected character '{}'.",
+            Self::UnterminatedString => "Unterminated string.",
+            Self::CloseString => "Close the string with a '\"'.",
+            Self::ExpectedExpression => "Expected expression.",
+            Self::ExpectIndex => "Expect index expression.",
+            Self::ExpectColonAfterThen => "Expect ':' after then branch of conditional expression.",
+            Self::ExpectMethodName => "Expect method name after '.'.",
+            Self::ExpectParenAfterMethod => "Expect '(' after method name.",
+            Self::ExpectBracketAfterSlice => "Expect ']' after slice.",
+            Self::ExpectBracketAfterIndex => "Expect ']' after index.",
+            Self::ExpectParenAfterArguments => "Expect ')' after arguments.",
+            Self::ExpectBracketAfterElements => "Expect ']' after list elements.",
+            Self::ExpectColonAfterKey => "Expect ':' after map key.",
+            Self::ExpectBraceAfterEntries => "Expect '}' after map entries.",
+            Self::ExpectParenAfterExpression => "Expect ')' after expression.",
+            Self::ExpectEndOfInput => "Expect end of input after expression.",
+            Self::ExpectLoopVariable => "Expect loop variable name after 'for'.",
+            Self::ExpectInAfterLoopVariable => "Expect 'in' after loop variable.",
+            Self::ExpectBraceBeforeBlock => "Expect '{' before block.",
+            Self::ExpectBraceAfterBlock => "Expect '}' after block.",
+            Self::ExpectColonAfterLabel => "Expect ':' after loop label.",
+            Self::ExpectLoopAfterLabel => "Expect 'for' after loop label.",
+            Self::ExpectParenAfterCatch => "Expect '(' after 'catch'.",
+            Self::ExpectCatchVariable => "Expect variable name for the caught error.",
+            Self::ExpectParenAfterCatchVariable => "Expect ')' after catch variable.",
+            Self::ExpectCatchOrFinally => "Expect 'catch' or 'finally' after 'try' block.",
+            Self::MatchDelimiter => "to match this '{}'",
+            Self::InvalidAssignmentTarget => "Invalid assignment target.",
+            Self::AssignableTargets => "Only indexed elements such as 'xs[0]' can be assigned to.",
+            Self::InvalidIncrementTarget => "Invalid increment target.",
+            Self::IncrementableTargets => {
+                "Only indexed elements such as 'xs[0]' can be incremented."
+            }
+            Self::ReadOnlyVariable => "Cannot change the variable '{}'.",
+            Self::VariablesAreReadOnly => {
+                "Loop and catch variables are read-only; keep values that change in a list or map."
+            }
+            Self::LoopControlOutsideLoop => "Can't use '{}' outside of a loop.",
+            Self::UndefinedVariable => "Undefined variable '{}'.",
+            Self::DidYouMean => "Did you mean '{}'?",
+            Self::UndefinedLabel => "Undefined loop label '{}'.",
+            Self::OperandsMustBeNumbers => "Operands must be numbers.",
+            Self::InvalidAddOperands => "The operands must be two numbers or two strings.",
+            Self::OperandMustBeNumber => "The operand must be a number.",
+            Self::OperandsMustBeIntegers => "Operands must be integers.",
+            Self::NotIndexable => "Only lists and maps can be indexed.",
+            Self::NotSliceable => "Only lists can be sliced.",
+            Self::ListIndexNotInteger => "List index must be an integer.",
+            Self::SliceBoundsNotIntegers => "Slice bounds must be integers.",
+            Self::UnhashableKey => "Unhashable map key of type '{}'.",
+            Self::HashableKeys => "Only strings, numbers, bools and nil can be map keys.",
+            Self::InvalidRangeBounds => "Range bounds must be integers.",
+            Self::NoMethods => "Type '{}' has no methods.",
+            Self::UndefinedMethod => "Undefined method '{}' for {}.",
+            Self::WrongArity => "Expected {} arguments but got {}.",
+            Self::NotIterable => "Cannot iterate over a value of type '{}'.",
+            Self::IterableValues => {
+                "Only lists, maps, strings, ranges and iterators can be iterated."
+            }
+            Self::IndexOutOfBounds => "List index {} out of bounds for length {}.",
+            Self::EmptyList => "Cannot pop from an empty list.",
+            Self::UnsupportedBinaryOperator => "Unsupported binary operator '{}'.",
+            Self::UnsupportedUnaryOperator => "Unsupported unary operator '{}'.",
+            Self::UnsupportedAssignmentOperator => "Unsupported assignment operator '{}'.",
+            Self::UncaughtThrow => "Uncaught error: {}",
+            Self::IteratorExhausted => "The iterator has no more values.",
+            Self::CheckDone => "Check 'done()' before calling 'next()'.",
+            Self::RemainderByZero => "Cannot take the remainder of a division by zero.",
+            Self::NestingTooDeep => "Expression nested too deeply (the limit is {}).",
+            Self::RaiseMaxNesting => "Raise the limit with --max-nesting.",
+            Self::StackOverflow => "Stack overflow: more than {} nested calls.",
+            Self::RaiseMaxCallDepth => "Raise the limit with --max-call-depth.",
+            Self::TooManyConstants => "Too many constants in one chunk (the limit is {}).",
+            Self::JumpTooFar => "Too much code to jump over (the limit is {} bytes).",
+            Self::TooManyElements => "Too many elements in one literal (the limit is {}).",
+            Self::TooManyArguments => "Too many arguments in one call (the limit is {}).",
+            Self::TooManyLocals => "Too many loop variables in scope (the limit is {}).",
+            Self::OutOfMemory => "Out of memory: the heap outgrew its limit of {} bytes.",
+            Self::RaiseMaxHeap => "Raise the limit with --max-heap.",
+            Self::UnreadableFile => "Unable to read '{}': {}",
+            Self::InvalidEncoding => "'{}' is not valid UTF-8 (invalid byte at offset {}).",
+            Self::UnwritableFile => "Unable to write '{}': {}",
+            Self::NotBytecode => "'{}' is not a compiled Strix script.",
+            Self::BytecodeVersion => {
+                "'{}' was compiled for bytecode version {}, but this Strix runs version {}."
+            }
+            Self::TruncatedBytecode => "'{}' is corrupt: malformed data at byte {}.",
+            Self::ChecksumMismatch => "'{}' is corrupt: its checksum does not match its contents.",
+            Self::InvalidBytecode => "'{}' contains invalid bytecode: {}.",
+            Self::BadOpcode => "unknown opcode {} at offset {}",
+            Self::BadOperand => "operand out of range at offset {}",
+            Self::BadJump => "jump out of the chunk at offset {}",
+            Self::StackUnderflow => "stack underflow at offset {}",
+            Self::StackMismatch => "paths reach offset {} with different stack depths",
+            Self::RunsPastEnd => "the code runs past the end of the chunk",
+            Self::Location => "[line {}:{}]",
+            Self::KindError => "{} error[{}]: {}",
+            Self::Help => "help: {}",
+            Self::Frame => "at {} ({}:{})",
+            Self::MoreFrames => "... and {} more",
+            Self::UnknownCommand => "Unknown command '{}'.",
+            Self::ClearScreenFailed => "Unable to clear the screen: {}",
+            Self::Bye => "Bye!",
+        }
+    }
+
+    fn pt_br(self) -> &'static str {
+        match self {
+            Self::UnexpectedCharacter => "Caractere inesperado '{}'.",
+            Self::UnterminatedString => "String não terminada.",
+            Self::CloseString => "Feche a string com um '\"'.",
+            Self::ExpectedExpression => "Esperava uma expressão.",
+            Self::ExpectIndex => "Esperava uma expressão de índice.",
+            Self::ExpectColonAfterThen => {
+                "Esperava ':' após o primeiro ramo da expressão condicional."
+            }
+            Self::ExpectMethodName => "Esperava o nome de um método após '.'.",
+            Self::ExpectParenAfterMethod => "Esperava '(' após o nome do método.",
+            Self::ExpectBracketAfterSlice => "Esperava ']' após a fatia.",
+            Self::ExpectBracketAfterIndex => "Esperava ']' após o índice.",
+            Self::ExpectParenAfterArguments => "Esperava ')' após os argumentos.",
+            Self::ExpectBracketAfterElements => "Esperava ']' após os elementos da lista.",
+            Self::ExpectColonAfterKey => "Esperava ':' após a chave do mapa.",
+            Self::ExpectBraceAfterEntries => "Esperava '}' após as entradas do mapa.",
+            Self::ExpectParenAfterExpression => "Esperava ')' após a expressão.",
+            Self::ExpectEndOfInput => "Esperava o fim da entrada após a expressão.",
+            Self::ExpectLoopVariable => "Esperava o nome da variável do laço após 'for'.",
+            Self::ExpectInAfterLoopVariable => "Esperava 'in' após a variável do laço.",
+            Self::ExpectBraceBeforeBlock => "Esperava '{' antes do bloco.",
+            Self::ExpectBraceAfterBlock => "Esperava '}' após o bloco.",
+            Self::ExpectColonAfterLabel => "Esperava ':' após o rótulo do laço.",
+            Self::ExpectLoopAfterLabel => "Esperava 'for' após o rótulo do laço.",
+            Self::ExpectParenAfterCatch => "Esperava '(' após 'catch'.",
+            Self::ExpectCatchVariable => "Esperava o nome da variável do erro capturado.",
+            Self::ExpectParenAfterCatchVariable => "Esperava ')' após a variável do 'catch'.",
+            Self::ExpectCatchOrFinally => "Esperava 'catch' ou 'finally' após o bloco 'try'.",
+            Self::MatchDelimiter => "para fechar este '{}'",
+            Self::InvalidAssignmentTarget => "Alvo de atribuição inválido.",
+            Self::AssignableTargets => {
+                "Só é possível atribuir a elementos indexados, como 'xs[0]'."
+            }
+            Self::InvalidIncrementTarget => "Alvo de incremento inválido.",
+            Self::IncrementableTargets => {
+                "Só é possível incrementar elementos indexados, como 'xs[0]'."
+            }
+            Self::ReadOnlyVariable => "Não é possível alterar a variável '{}'.",
+            Self::VariablesAreReadOnly => {
+                "Variáveis de laço e de catch são somente leitura; guarde valores que mudam em \
+                 uma lista ou mapa."
+            }
+            Self::LoopControlOutsideLoop => "Não é possível usar '{}' fora de um laço.",
+            Self::UndefinedVariable => "Variável '{}' não definida.",
+            Self::DidYouMean => "Você quis dizer '{}'?",
+            Self::UndefinedLabel => "Rótulo de laço '{}' não definido.",
+            Self::OperandsMustBeNumbers => "Os operandos devem ser números.",
+            Self::InvalidAddOperands => "Os operandos devem ser dois números ou duas strings.",
+            Self::OperandMustBeNumber => "O operando deve ser um número.",
+            Self::OperandsMustBeIntegers => "Os operandos devem ser inteiros.",
+            Self::NotIndexable => "Só listas e mapas podem ser indexados.",
+            Self::NotSliceable => "Só listas podem ser fatiadas.",
+            Self::ListIndexNotInteger => "O índice de uma lista deve ser um inteiro.",
+            Self::SliceBoundsNotIntegers => "Os limites de uma fatia devem ser inteiros.",
+            Self::UnhashableKey => "Um valor do tipo '{}' não pode ser chave de mapa.",
+            Self::HashableKeys => "Só strings, números, bools e nil podem ser chaves de mapa.",
+            Self::InvalidRangeBounds => "Os limites de um intervalo devem ser inteiros.",
+            Self::NoMethods => "O tipo '{}' não tem métodos.",
+            Self::UndefinedMethod => "Método '{}' não definido para {}.",
+            Self::WrongArity => "Esperava {} argumentos, mas recebeu {}.",
+            Self::NotIterable => "Não é possível iterar sobre um valor do tipo '{}'.",
+            Self::IterableValues => {
+                "Só listas, mapas, strings, intervalos e iteradores podem ser iterados."
+            }
+            Self::IndexOutOfBounds => "Índice {} fora dos limites de uma lista de tamanho {}.",
+            Self::EmptyList => "Não é possível remover de uma lista vazia.",
+            Self::UnsupportedBinaryOperator => "Operador binário '{}' não suportado.",
+            Self::UnsupportedUnaryOperator => "Operador unário '{}' não suportado.",
+            Self::UnsupportedAssignmentOperator => "Operador de atribuição '{}' não suportado.",
+            Self::UncaughtThrow => "Erro não capturado: {}",
+            Self::IteratorExhausted => "O iterador não tem mais valores.",
+            Self::CheckDone => "Verifique 'done()' antes de chamar 'next()'.",
+            Self::RemainderByZero => "Não é possível obter o resto de uma divisão por zero.",
+            Self::NestingTooDeep => "Expressão aninhada demais (o limite é {}).",
+            Self::RaiseMaxNesting => "Aumente o limite com --max-nesting.",
+            Self::StackOverflow => "Estouro de pilha: mais de {} chamadas aninhadas.",
+            Self::RaiseMaxCallDepth => "Aumente o limite com --max-call-depth.",
+            Self::TooManyConstants => "Constantes demais em um só trecho (o limite é {}).",
+            Self::JumpTooFar => "Código demais para saltar (o limite é {} bytes).",
+            Self::TooManyElements => "Elementos demais em um só literal (o limite é {}).",
+            Self::TooManyArguments => "Argumentos demais em uma só chamada (o limite é {}).",
+            Self::TooManyLocals => "Variáveis de laço demais no escopo (o limite é {}).",
+            Self::OutOfMemory => "Memória esgotada: o heap excedeu seu limite de {} bytes.",
+            Self::RaiseMaxHeap => "Aumente o limite com --max-heap.",
+            Self::UnreadableFile => "Não foi possível ler '{}': {}",
+            Self::InvalidEncoding => "'{}' não é UTF-8 válido (byte inválido na posição {}).",
+            Self::UnwritableFile => "Não foi possível escrever '{}': {}",
+            Self::NotBytecode => "'{}' não é um script Strix compilado.",
+            Self::BytecodeVersion => {
+                "'{}' foi compilado para a versão {} do bytecode, mas este Strix executa a versão {}."
+            }
+            Self::TruncatedBytecode => "'{}' está corrompido: dados malformados no byte {}.",
+            Self::ChecksumMismatch => {
+                "'{}' está corrompido: o checksum não confere com o conteúdo."
+            }
+            Self::InvalidBytecode => "'{}' contém bytecode inválido: {}.",
+            Self::BadOpcode => "opcode desconhecido {} na posição {}",
+            Self::BadOperand => "operando fora do intervalo na posição {}",
+            Self::BadJump => "salto para fora do trecho na posição {}",
+            Self::StackUnderflow => "pilha vazia demais na posição {}",
+            Self::StackMismatch => {
+                "caminhos chegam à posição {} com profundidades de pilha diferentes"
+            }
+            Self::RunsPastEnd => "o código passa do fim do trecho",
+            Self::Location => "[linha {}:{}]",
+            Self::KindError => "{} [{}]: {}",
+            Self::Help => "ajuda: {}",
+            Self::Frame => "em {} ({}:{})",
+            Self::MoreFrames => "... e mais {}",
+            Self::UnknownCommand => "Comando '{}' desconhecido.",
+            Self::ClearScreenFailed => "Não foi possível limpar a tela: {}",
+            Self::Bye => "Tchau!",
+        }
+    }
+}
+
+/// The name of an error kind as it appears in the header of a diagnostic.
+pub fn kind_name(locale: Locale, kind: ErrorKind) -> &'static str {
+    match locale {
+        Locale::En => match kind {
+            ErrorKind::Lexical => "Lexical",
+            ErrorKind::Syntax => "Syntax",
+            ErrorKind::Resolve => "Resolve",
+            ErrorKind::Type => "Type",
+            ErrorKind::Runtime => "Runtime",
+            ErrorKind::Io => "IO",
+            ErrorKind::Limit => "Limit",
+        },
+        Locale::PtBr => match kind {
+            ErrorKind::Lexical => "Erro léxico",
+            ErrorKind::Syntax => "Erro de sintaxe",
+            ErrorKind::Resolve => "Erro de resolução",
+            ErrorKind::Type => "Erro de tipo",
+            ErrorKind::Runtime => "Erro de execução",
+            ErrorKind::Io => "Erro de E/S",
+            ErrorKind::Limit => "Erro de limite",
+        },
+    }
+}
+
+/// Translates a message into the current locale, filling its placeholders with `args`.
+pub fn tr(msg: Msg, args: &[&dyn Display]) -> String {
+    tr_in(locale(), msg, args)
+}
+
+/// Translates a message into `locale`, filling its placeholders with `args`.
+pub fn tr_in(locale: Locale, msg: Msg, args: &[&dyn Display]) -> String {
+    let template = match locale {
+        Locale::En => msg.en(),
+        Locale::PtBr => msg.pt_br(),
+    };
+
+    let mut buffer = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() {
+        buffer.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            buffer.push_str(&arg.to_string());
+        }
+        buffer.push_str(part);
+    }
+
+    buffer
+}
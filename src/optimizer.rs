@@ -0,0 +1,406 @@
+use std::borrow::Cow;
+
+use crate::{
+    expr::Expr,
+    interpreter,
+    tokenizer::{Literal, Token},
+    value::Value,
+    visitor::Visitor,
+};
+
+/// The optimization level used unless configured otherwise.
+pub const DEFAULT_LEVEL: u8 = 1;
+/// The highest optimization level. Higher levels behave like this one.
+pub const MAX_LEVEL: u8 = 2;
+
+/// Rewrites a syntax tree into an equivalent, cheaper one.
+///
+/// From level 1, operators whose operands are all literals are evaluated once here, and
+/// groupings are dropped as the tree already encodes precedence. An operation that would fail
+/// is left in place, so its error is still raised at run time and at the same location.
+///
+/// From level 2, dead code is removed. Conditionals and coalescing whose outcome is known are
+/// replaced by the branch they would take, which covers literal conditions and list and map
+/// literals without effects, as those are always truthy and never nil. A block drops what
+/// follows a `break`, `continue` or `throw`, which never runs, and the values it discards
+/// that were made without effects. An operator whose first operand never finishes is replaced
+/// by that operand. The language has no functions, so there are no unused functions to drop
+/// and no calls to inline; only methods of built-in values are called.
+///
+/// Level 0 leaves the tree as it is.
+pub struct Optimizer {
+    level: u8,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            level: DEFAULT_LEVEL,
+        }
+    }
+
+    pub fn set_level(&mut self, level: u8) {
+        self.level = level;
+    }
+
+    pub fn optimize<'a>(&mut self, expr: &Expr<'a>) -> Expr<'a> {
+        expr.accept(self)
+    }
+
+    fn folds(&self) -> bool {
+        self.level >= 1
+    }
+
+    fn eliminates_dead_code(&self) -> bool {
+        self.level >= 2
+    }
+
+    fn optimize_all<'a>(&mut self, exprs: &[Expr<'a>]) -> Vec<Expr<'a>> {
+        exprs.iter().map(|expr| self.optimize(expr)).collect()
+    }
+
+    fn boxed<'a>(&mut self, expr: &Expr<'a>) -> Box<Expr<'a>> {
+        Box::new(self.optimize(expr))
+    }
+}
+
+/// Whether evaluating the expression cannot fail or have effects, so that it need not be
+/// evaluated when its value is unused.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => true,
+        Expr::Grouping { expression } => is_pure(expression),
+        Expr::List { elements, .. } => elements.iter().all(is_pure),
+        // Every literal can be a key.
+        Expr::Map { entries, .. } => entries
+            .iter()
+            .all(|(key, value)| matches!(key, Expr::Literal { .. }) && is_pure(value)),
+        _ => false,
+    }
+}
+
+/// Whether the expression never finishes with a value, as it always breaks, continues or
+/// throws.
+fn diverges(expr: &Expr) -> bool {
+    match expr {
+        Expr::Break { .. } | Expr::Continue { .. } | Expr::Throw { .. } => true,
+        Expr::Grouping { expression } => diverges(expression),
+        Expr::Block { expressions, .. } => expressions.iter().any(diverges),
+        _ => false,
+    }
+}
+
+/// The value the expression is known to evaluate to without effects, as far as conditions
+/// and coalescing can tell: whether it is truthy, and whether it is nil.
+fn known_outcome(expr: &Expr) -> Option<(bool, bool)> {
+    match expr {
+        Expr::Literal { value } => Some((
+            interpreter::is_truthy(&value.clone().into()),
+            *value == Literal::Nil,
+        )),
+        Expr::List { .. } | Expr::Map { .. } if is_pure(expr) => Some((true, false)),
+        _ => None,
+    }
+}
+
+/// The literal a folded value is written as, if it has one.
+fn literal(value: Value) -> Option<Literal<'static>> {
+    match value {
+        Value::Nil => Some(Literal::Nil),
+        Value::Bool(b) => Some(Literal::Bool(b)),
+        Value::Number(n) => Some(Literal::Number(n)),
+        Value::String(s) => Some(Literal::String(Cow::Owned(s.to_string()))),
+        Value::List(_) | Value::Map(_) | Value::Range(_) | Value::Iterator(_) | Value::Error(_) => {
+            None
+        }
+    }
+}
+
+impl<'a> Visitor<'a, Expr<'a>> for Optimizer {
+    fn visit_binary_expr(
+        &mut self,
+        left: &Expr<'a>,
+        operator: &Token<'a>,
+        right: &Expr<'a>,
+    ) -> Expr<'a> {
+        let left = self.optimize(left);
+        if self.eliminates_dead_code() && diverges(&left) {
+            return left;
+        }
+        let right = self.optimize(right);
+
+        if let (true, Expr::Literal { value: l }, Expr::Literal { value: r }) =
+            (self.folds(), &left, &right)
+        {
+            let folded = interpreter::binary_op(operator, l.clone().into(), r.clone().into());
+            if let Some(value) = folded.ok().and_then(literal) {
+                return Expr::new_literal(value);
+            }
+        }
+
+        Expr::new_binary(Box::new(left), operator.clone(), Box::new(right))
+    }
+
+    fn visit_grouping_expr(&mut self, expression: &Expr<'a>) -> Expr<'a> {
+        let expression = self.optimize(expression);
+        if self.folds() {
+            return expression;
+        }
+
+        Expr::new_grouping(Box::new(expression))
+    }
+
+    fn visit_literal_expr(&mut self, value: &Literal<'a>) -> Expr<'a> {
+        Expr::new_literal(value.clone())
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> Expr<'a> {
+        let right = self.optimize(right);
+
+        if let (true, Expr::Literal { value }) = (self.folds(), &right) {
+            let folded = interpreter::unary_op(operator, value.clone().into());
+            if let Some(value) = folded.ok().and_then(literal) {
+                return Expr::new_literal(value);
+            }
+        }
+
+        Expr::new_unary(operator.clone(), Box::new(right))
+    }
+
+    fn visit_ternary_expr(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Expr<'a>,
+        else_branch: &Expr<'a>,
+    ) -> Expr<'a> {
+        let condition = self.optimize(condition);
+
+        if self.eliminates_dead_code() {
+            if diverges(&condition) {
+                return condition;
+            }
+            if let Some((truthy, _)) = known_outcome(&condition) {
+                return self.optimize(if truthy { then_branch } else { else_branch });
+            }
+        }
+
+        Expr::new_ternary(
+            Box::new(condition),
+            self.boxed(then_branch),
+            self.boxed(else_branch),
+        )
+    }
+
+    fn visit_coalesce_expr(
+        &mut self,
+        left: &Expr<'a>,
+        operator: &Token<'a>,
+        right: &Expr<'a>,
+    ) -> Expr<'a> {
+        let left = self.optimize(left);
+
+        if self.eliminates_dead_code() {
+            if diverges(&left) {
+                return left;
+            }
+            if let Some((_, nil)) = known_outcome(&left) {
+                return if nil { self.optimize(right) } else { left };
+            }
+        }
+
+        Expr::new_coalesce(Box::new(left), operator.clone(), self.boxed(right))
+    }
+
+    fn visit_range_expr(
+        &mut self,
+        start: &Expr<'a>,
+        operator: &Token<'a>,
+        end: &Expr<'a>,
+    ) -> Expr<'a> {
+        Expr::new_range(self.boxed(start), operator.clone(), self.boxed(end))
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token<'a>, elements: &[Expr<'a>]) -> Expr<'a> {
+        Expr::new_list(bracket.clone(), self.optimize_all(elements))
+    }
+
+    fn visit_map_expr(&mut self, brace: &Token<'a>, entries: &[(Expr<'a>, Expr<'a>)]) -> Expr<'a> {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| (self.optimize(key), self.optimize(value)))
+            .collect();
+        Expr::new_map(brace.clone(), entries)
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+    ) -> Expr<'a> {
+        Expr::new_index(self.boxed(object), bracket.clone(), self.boxed(index))
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        start: Option<&Expr<'a>>,
+        end: Option<&Expr<'a>>,
+    ) -> Expr<'a> {
+        Expr::new_slice(
+            self.boxed(object),
+            bracket.clone(),
+            start.map(|start| self.boxed(start)),
+            end.map(|end| self.boxed(end)),
+        )
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        operator: &Token<'a>,
+        value: &Expr<'a>,
+    ) -> Expr<'a> {
+        Expr::new_set_index(
+            self.boxed(object),
+            bracket.clone(),
+            self.boxed(index),
+            operator.clone(),
+            self.boxed(value),
+        )
+    }
+
+    fn visit_increment_index_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        operator: &Token<'a>,
+        prefix: bool,
+    ) -> Expr<'a> {
+        Expr::new_increment_index(
+            self.boxed(object),
+            bracket.clone(),
+            self.boxed(index),
+            operator.clone(),
+            prefix,
+        )
+    }
+
+    fn visit_invoke_expr(
+        &mut self,
+        object: &Expr<'a>,
+        name: &Token<'a>,
+        arguments: &[Expr<'a>],
+        optional: bool,
+    ) -> Expr<'a> {
+        let object = self.optimize(object);
+
+        // An optional call on an object known to be nil is skipped, and one on an object known
+        // not to be is an ordinary call.
+        let mut optional = optional;
+        if optional
+            && self.eliminates_dead_code()
+            && let Some((_, nil)) = known_outcome(&object)
+        {
+            if nil {
+                return Expr::new_literal(Literal::Nil);
+            }
+            optional = false;
+        }
+
+        Expr::new_invoke(
+            Box::new(object),
+            name.clone(),
+            self.optimize_all(arguments),
+            optional,
+        )
+    }
+
+    fn visit_throw_expr(&mut self, keyword: &Token<'a>, value: &Expr<'a>) -> Expr<'a> {
+        Expr::new_throw(keyword.clone(), self.boxed(value))
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token<'a>, slot: usize) -> Expr<'a> {
+        Expr::new_variable(name.clone(), slot)
+    }
+
+    fn visit_block_expr(&mut self, brace: &Token<'a>, expressions: &[Expr<'a>]) -> Expr<'a> {
+        let mut expressions = self.optimize_all(expressions);
+
+        if self.eliminates_dead_code() {
+            if let Some(end) = expressions.iter().position(diverges) {
+                expressions.truncate(end + 1);
+            }
+
+            // Only the last value is kept, so the others need not be made unless it has effects.
+            let last = expressions.pop();
+            expressions.retain(|expr| !is_pure(expr));
+            expressions.extend(last);
+
+            // Blocks do not scope variables, so a lone expression can stand on its own.
+            if expressions.len() == 1
+                && let Some(expr) = expressions.pop()
+            {
+                return expr;
+            }
+        }
+
+        Expr::new_block(brace.clone(), expressions)
+    }
+
+    fn visit_for_expr(
+        &mut self,
+        label: Option<&Token<'a>>,
+        keyword: &Token<'a>,
+        variable: &Token<'a>,
+        iterable: &Expr<'a>,
+        body: &Expr<'a>,
+        collect: bool,
+    ) -> Expr<'a> {
+        Expr::new_for(
+            label.cloned(),
+            keyword.clone(),
+            variable.clone(),
+            self.boxed(iterable),
+            self.boxed(body),
+            collect,
+        )
+    }
+
+    fn visit_break_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        label: Option<&Token<'a>>,
+        loops: usize,
+    ) -> Expr<'a> {
+        Expr::new_break(keyword.clone(), label.cloned(), loops)
+    }
+
+    fn visit_continue_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        label: Option<&Token<'a>>,
+        loops: usize,
+    ) -> Expr<'a> {
+        Expr::new_continue(keyword.clone(), label.cloned(), loops)
+    }
+
+    fn visit_try_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        body: &Expr<'a>,
+        handler: Option<(&Token<'a>, &Expr<'a>)>,
+        finally: Option<&Expr<'a>>,
+    ) -> Expr<'a> {
+        Expr::new_try(
+            keyword.clone(),
+            self.boxed(body),
+            handler.map(|(variable, handler)| (variable.clone(), self.boxed(handler))),
+            finally.map(|finally| self.boxed(finally)),
+        )
+    }
+}
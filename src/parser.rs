@@ -1,29 +1,197 @@
+use std::iter::Peekable;
+
 use crate::{
-    err::StrixError,
+    err::{ErrorCode, Span, StrixError},
     expr::Expr,
-    tokenizer::{Literal, Token, TokenType},
+    interner::{Interner, Symbol},
+    messages::{Msg, tr},
+    suggest,
+    tokenizer::{self, Literal, Token, TokenType},
 };
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+/// How deeply expressions may nest before parsing fails, unless configured otherwise.
+/// Every level costs stack in the parser and in every pass that walks the tree, so this
+/// is kept well within the 8 MiB main thread of an unoptimized build.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The tokens a parser pulls from.
+type Tokens<'a> = Box<dyn Iterator<Item = Result<Token<'a>, StrixError>> + 'a>;
+
+/// Parses tokens as it pulls them from a scanner, looking one token ahead, or two where an
+/// operator is ambiguous.
+pub struct Parser<'a> {
+    tokens: Peekable<Tokens<'a>>,
+    current: Token<'a>,
+    previous: Token<'a>,
+    scan_error: Option<StrixError>,
+    depth: usize,
+    max_depth: usize,
+    /// Variable names and loop labels, so that scopes compare them by symbol.
+    names: Interner,
+    /// The variables in scope, innermost last, each at the slot it is read from. Loops keep
+    /// their iterator in an unnamed slot below their variable.
+    locals: Vec<Option<Symbol>>,
+    /// The labels of the loops around the expression being parsed, without their quote,
+    /// innermost last.
+    loops: Vec<Option<Symbol>>,
 }
 
-impl Parser {
-    pub fn from(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: impl Iterator<Item = Result<Token<'a>, StrixError>> + 'a) -> Self {
+        let eof = Token::new(TokenType::Eof, "", None, 1);
+        let mut parser = Self {
+            tokens: (Box::new(tokens) as Tokens<'a>).peekable(),
+            current: eof.clone(),
+            previous: eof,
+            scan_error: None,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            names: Interner::new(),
+            locals: vec![],
+            loops: vec![],
+        };
+        parser.current = parser.pull();
+        parser
+    }
+
+    /// Parses tokens that were already scanned. Only the tests start from a token list.
+    #[cfg(test)]
+    pub fn from(tokens: Vec<Token<'a>>) -> Self {
+        Self::new(tokens.into_iter().map(Ok))
+    }
+
+    /// Limits how deeply expressions may nest, which bounds the recursion of everything
+    /// that later walks the syntax tree.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Counts one more level of nesting, failing once the limit is exceeded.
+    fn enter(&mut self) -> Result<(), StrixError> {
+        if self.depth >= self.max_depth {
+            return Err(StrixError::at(
+                ErrorCode::NestingTooDeep,
+                self.peek(),
+                tr(Msg::NestingTooDeep, &[&self.max_depth]),
+            )
+            .with_note(tr(Msg::RaiseMaxNesting, &[])));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Parses a subexpression one level deeper. Nesting counted while parsing it belongs
+    /// to the subexpression and is released afterwards.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Expr<'a>, StrixError>,
+    ) -> Result<Expr<'a>, StrixError> {
+        let depth = self.depth;
+        self.enter()?;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    fn expression(&mut self) -> Result<Expr<'a>, StrixError> {
+        self.nested(Self::assignment)
     }
 
-    fn expression(&mut self) -> Result<Expr, StrixError> {
-        self.equality()
+    fn assignment(&mut self) -> Result<Expr<'a>, StrixError> {
+        let expr = self.ternary()?;
+
+        if self.expect(&[
+            TokenType::Equal,
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+            TokenType::AmpersandEqual,
+            TokenType::PipeEqual,
+            TokenType::CaretEqual,
+            TokenType::LessLessEqual,
+            TokenType::GreaterGreaterEqual,
+        ]) {
+            let operator = self.previous().clone();
+            let value = self.nested(Self::assignment)?;
+
+            if let Expr::Index {
+                object,
+                bracket,
+                index,
+            } = expr
+            {
+                return Ok(Expr::new_set_index(
+                    object,
+                    bracket,
+                    index,
+                    operator,
+                    Box::new(value),
+                ));
+            }
+
+            if let Expr::Variable { name, .. } = expr {
+                return Err(read_only(
+                    ErrorCode::InvalidAssignmentTarget,
+                    &operator,
+                    &name,
+                ));
+            }
+
+            return Err(StrixError::at(
+                ErrorCode::InvalidAssignmentTarget,
+                &operator,
+                tr(Msg::InvalidAssignmentTarget, &[]),
+            )
+            .with_note(tr(Msg::AssignableTargets, &[])));
+        }
+
+        Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr, StrixError> {
+    fn ternary(&mut self) -> Result<Expr<'a>, StrixError> {
+        let condition = self.coalesce()?;
+
+        if self.expect(&[TokenType::Question]) {
+            let then_branch = self.expression()?;
+            self.consume(&TokenType::Colon, tr(Msg::ExpectColonAfterThen, &[]))?;
+            let else_branch = self.nested(Self::ternary)?;
+            return Ok(Expr::new_ternary(
+                Box::new(condition),
+                Box::new(then_branch),
+                Box::new(else_branch),
+            ));
+        }
+
+        Ok(condition)
+    }
+
+    fn coalesce(&mut self) -> Result<Expr<'a>, StrixError> {
+        let mut expr = self.equality();
+
+        while self.expect(&[TokenType::QuestionQuestion]) {
+            self.enter()?;
+            let operator = self.previous().clone();
+            let right = self.nested(Self::equality)?;
+            expr = Ok(Expr::new_coalesce(
+                Box::new(expr?),
+                operator,
+                Box::new(right),
+            ));
+        }
+
+        expr
+    }
+
+    fn equality(&mut self) -> Result<Expr<'a>, StrixError> {
         let mut expr = self.comparision();
 
         while self.expect(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous();
-            let right = self.comparision()?;
+            self.enter()?;
+            let operator = self.previous().clone();
+            let right = self.nested(Self::comparision)?;
             expr = Ok(Expr::new_binary(Box::new(expr?), operator, Box::new(right)));
         }
 
@@ -38,7 +206,7 @@ impl Parser {
             }
         }
 
-        return false;
+        false
     }
 
     fn check(&self, token_type: &TokenType) -> bool {
@@ -49,28 +217,64 @@ impl Parser {
         self.peek().token_type == *token_type
     }
 
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> &Token<'a> {
         if !self.is_at_end() {
-            self.current += 1;
+            let next = self.pull();
+            self.previous = std::mem::replace(&mut self.current, next);
         }
 
-        return self.previous();
+        self.previous()
+    }
+
+    /// Takes the next token from the scanner. The grammar stops at an end-of-file token, so
+    /// one stands in when the tokens run out or scanning fails, and the error is kept for
+    /// `parse` to report.
+    fn pull(&mut self) -> Token<'a> {
+        match self.tokens.next() {
+            Some(Ok(token)) => token,
+            Some(Err(err)) => {
+                self.scan_error.get_or_insert(err);
+                Token::new(TokenType::Eof, "", None, self.current.span.line)
+            }
+            None => Token::new(TokenType::Eof, "", None, self.current.span.line),
+        }
+    }
+
+    /// The type of the token after the current one, or `Eof` if there is none.
+    fn peek_next(&mut self) -> TokenType {
+        match self.tokens.peek() {
+            Some(Ok(token)) => token.token_type,
+            _ => TokenType::Eof,
+        }
+    }
+
+    /// Consumes the first half of a `++` or `--` that is not an increment, leaving the second
+    /// half as the current token.
+    fn split_increment(&mut self) -> bool {
+        if !self.check(&TokenType::PlusPlus) && !self.check(&TokenType::MinusMinus) {
+            return false;
+        }
+
+        let (first, second) = split(&self.current);
+        self.previous = first;
+        self.current = second;
+        true
     }
 
     fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
-    fn peek(&self) -> Token {
-        self.tokens.get(self.current).unwrap().clone()
+    fn peek(&self) -> &Token<'a> {
+        &self.current
     }
 
-    fn previous(&self) -> Token {
-        self.tokens.get(self.current - 1).unwrap().clone()
+    fn previous(&self) -> &Token<'a> {
+        &self.previous
     }
 
-    fn comparision(&mut self) -> Result<Expr, StrixError> {
-        let mut expr = self.term();
+    fn comparision(&mut self) -> Result<Expr<'a>, StrixError> {
+        let mut expr = self.range();
 
         while self.expect(&[
             TokenType::Greater,
@@ -78,49 +282,250 @@ impl Parser {
             TokenType::Less,
             TokenType::LessEqual,
         ]) {
-            let operator = self.previous();
-            let right = self.term()?;
+            self.enter()?;
+            let operator = self.previous().clone();
+            let right = self.nested(Self::range)?;
             expr = Ok(Expr::new_binary(Box::new(expr?), operator, Box::new(right)));
         }
 
         expr
     }
 
-    fn term(&mut self) -> Result<Expr, StrixError> {
+    fn range(&mut self) -> Result<Expr<'a>, StrixError> {
+        let start = self.bitwise()?;
+
+        if self.expect(&[TokenType::DotDot, TokenType::DotDotEqual]) {
+            self.enter()?;
+            let operator = self.previous().clone();
+            let end = self.nested(Self::bitwise)?;
+            return Ok(Expr::new_range(Box::new(start), operator, Box::new(end)));
+        }
+
+        Ok(start)
+    }
+
+    /// Parses the bitwise and shift operators. From the loosest, their levels are `|`, `^`, `&`
+    /// and then `<<` and `>>`, all binding tighter than comparisons so that `x & 1 == 0` tests
+    /// the low bit. The levels are resolved with a stack of pending operators rather than a
+    /// function each, which keeps every level of nesting cheap in native stack.
+    fn bitwise(&mut self) -> Result<Expr<'a>, StrixError> {
+        let mut operands = vec![self.term()?];
+        let mut operators: Vec<Token<'a>> = vec![];
+
+        while let Some(precedence) = bitwise_precedence(self.peek().token_type) {
+            self.advance();
+            self.enter()?;
+            let operator = self.previous().clone();
+            while let Some(pending) = operators.pop_if(|pending| {
+                bitwise_precedence(pending.token_type).is_some_and(|p| p >= precedence)
+            }) {
+                let right = operands.pop().unwrap();
+                let left = operands.pop().unwrap();
+                operands.push(Expr::new_binary(Box::new(left), pending, Box::new(right)));
+            }
+            operators.push(operator);
+            operands.push(self.nested(Self::term)?);
+        }
+
+        while let Some(pending) = operators.pop() {
+            let right = operands.pop().unwrap();
+            let left = operands.pop().unwrap();
+            operands.push(Expr::new_binary(Box::new(left), pending, Box::new(right)));
+        }
+        Ok(operands.pop().unwrap())
+    }
+
+    fn term(&mut self) -> Result<Expr<'a>, StrixError> {
         let mut expr = self.factor();
 
-        while self.expect(&[TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous();
-            let right = self.factor()?;
+        while self.expect(&[TokenType::Minus, TokenType::Plus]) || self.split_increment() {
+            self.enter()?;
+            let operator = self.previous().clone();
+            let right = self.nested(Self::factor)?;
             expr = Ok(Expr::new_binary(Box::new(expr?), operator, Box::new(right)));
         }
 
         expr
     }
 
-    fn factor(&mut self) -> Result<Expr, StrixError> {
+    fn factor(&mut self) -> Result<Expr<'a>, StrixError> {
         let mut expr = self.unary();
 
-        while self.expect(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous();
-            let right = self.unary()?;
+        while self.expect(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) {
+            self.enter()?;
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
             expr = Ok(Expr::new_binary(Box::new(expr?), operator, Box::new(right)));
         }
 
         expr
     }
 
-    fn unary(&mut self) -> Result<Expr, StrixError> {
+    fn unary(&mut self) -> Result<Expr<'a>, StrixError> {
         if self.expect(&[TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous();
-            let right = self.unary();
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary);
             return Ok(Expr::new_unary(operator, Box::new(right?)));
         }
 
-        self.primary()
+        if self.expect(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            let target = self.nested(Self::unary)?;
+
+            // Only a place can be decremented, so `--1` negates twice. A variable is reported as
+            // read-only instead.
+            if operator.token_type == TokenType::MinusMinus
+                && !matches!(target, Expr::Index { .. } | Expr::Variable { .. })
+            {
+                let (outer, inner) = split(&operator);
+                let negated = Expr::new_unary(inner, Box::new(target));
+                return Ok(Expr::new_unary(outer, Box::new(negated)));
+            }
+            return self.increment(target, operator, true);
+        }
+
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr<'a>, StrixError> {
+        let expr = self.call()?;
+
+        // After anything but a place, an operand following `--` makes it a binary minus and a
+        // negation, as in `1--1`, which `term` splits apart.
+        if !matches!(expr, Expr::Index { .. }) && starts_operand(self.peek_next()) {
+            return Ok(expr);
+        }
+
+        if self.expect(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            return self.increment(expr, operator, false);
+        }
+
+        Ok(expr)
+    }
+
+    fn increment(
+        &mut self,
+        target: Expr<'a>,
+        operator: Token<'a>,
+        prefix: bool,
+    ) -> Result<Expr<'a>, StrixError> {
+        if let Expr::Index {
+            object,
+            bracket,
+            index,
+        } = target
+        {
+            return Ok(Expr::new_increment_index(
+                object, bracket, index, operator, prefix,
+            ));
+        }
+
+        if let Expr::Variable { name, .. } = target {
+            return Err(read_only(
+                ErrorCode::InvalidIncrementTarget,
+                &operator,
+                &name,
+            ));
+        }
+
+        Err(StrixError::at(
+            ErrorCode::InvalidIncrementTarget,
+            &operator,
+            tr(Msg::InvalidIncrementTarget, &[]),
+        )
+        .with_note(tr(Msg::IncrementableTargets, &[])))
+    }
+
+    fn call(&mut self) -> Result<Expr<'a>, StrixError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.check(&TokenType::LeftBracket)
+                || self.check(&TokenType::Dot)
+                || self.check(&TokenType::QuestionDot)
+            {
+                self.enter()?;
+            }
+
+            if self.expect(&[TokenType::LeftBracket]) {
+                expr = self.finish_index(expr)?;
+            } else if self.expect(&[TokenType::Dot, TokenType::QuestionDot]) {
+                let optional = self.previous().token_type == TokenType::QuestionDot;
+                let name = self.consume(&TokenType::Identifier, tr(Msg::ExpectMethodName, &[]))?;
+                let paren =
+                    self.consume(&TokenType::LeftParen, tr(Msg::ExpectParenAfterMethod, &[]))?;
+                let arguments = self.arguments(&paren)?;
+                expr = Expr::new_invoke(Box::new(expr), name, arguments, optional);
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_index(&mut self, object: Expr<'a>) -> Result<Expr<'a>, StrixError> {
+        let bracket = self.previous().clone();
+
+        let start = if self.check(&TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+
+        if self.expect(&[TokenType::Colon]) {
+            let end = if self.check(&TokenType::RightBracket) {
+                None
+            } else {
+                Some(Box::new(self.expression()?))
+            };
+            self.close(
+                &TokenType::RightBracket,
+                &bracket,
+                tr(Msg::ExpectBracketAfterSlice, &[]),
+            )?;
+            return Ok(Expr::new_slice(Box::new(object), bracket, start, end));
+        }
+
+        self.close(
+            &TokenType::RightBracket,
+            &bracket,
+            tr(Msg::ExpectBracketAfterIndex, &[]),
+        )?;
+
+        match start {
+            Some(index) => Ok(Expr::new_index(Box::new(object), bracket, index)),
+            None => Err(StrixError::at(
+                ErrorCode::ExpectedExpression,
+                &bracket,
+                tr(Msg::ExpectIndex, &[]),
+            )),
+        }
+    }
+
+    fn arguments(&mut self, paren: &Token<'a>) -> Result<Vec<Expr<'a>>, StrixError> {
+        let mut arguments = Vec::new();
+
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                arguments.push(self.expression()?);
+                if !self.expect(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.close(
+            &TokenType::RightParen,
+            paren,
+            tr(Msg::ExpectParenAfterArguments, &[]),
+        )?;
+
+        Ok(arguments)
     }
 
-    fn primary(&mut self) -> Result<Expr, StrixError> {
+    fn primary(&mut self) -> Result<Expr<'a>, StrixError> {
         if self.expect(&[TokenType::False]) {
             return Ok(Expr::new_literal(Literal::Bool(false)));
         }
@@ -131,35 +536,310 @@ impl Parser {
             return Ok(Expr::new_literal(Literal::Nil));
         }
 
+        // `throw` is an expression, so it can supply a fallback as in `value ?? throw "missing"`.
+        if self.expect(&[TokenType::Throw]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            return Ok(Expr::new_throw(keyword, Box::new(value)));
+        }
+
+        if self.expect(&[TokenType::Break, TokenType::Continue]) {
+            return self.loop_control();
+        }
+
+        if self.expect(&[TokenType::Label]) {
+            let label = self.previous().clone();
+            self.consume(&TokenType::Colon, tr(Msg::ExpectColonAfterLabel, &[]))?;
+            self.consume(&TokenType::For, tr(Msg::ExpectLoopAfterLabel, &[]))?;
+            return self.for_loop(Some(label));
+        }
+
+        if self.expect(&[TokenType::For]) {
+            return self.for_loop(None);
+        }
+
+        if self.expect(&[TokenType::Try]) {
+            return self.try_catch();
+        }
+
         if self.expect(&[TokenType::Number, TokenType::String]) {
             return Ok(Expr::new_literal(
-                self.previous().literal.unwrap_or(Literal::Nil),
+                self.previous().literal.clone().unwrap_or(Literal::Nil),
             ));
         }
 
+        if self.expect(&[TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = Vec::new();
+
+            while !self.check(&TokenType::RightBracket) {
+                elements.push(self.expression()?);
+                if !self.expect(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            self.close(
+                &TokenType::RightBracket,
+                &bracket,
+                tr(Msg::ExpectBracketAfterElements, &[]),
+            )?;
+            return Ok(Expr::new_list(bracket, elements));
+        }
+
+        // Blocks only follow keywords such as `for`, so a brace in expression position always
+        // opens a map.
+        if self.expect(&[TokenType::LeftBrace]) {
+            let brace = self.previous().clone();
+            let mut entries = Vec::new();
+
+            while !self.check(&TokenType::RightBrace) {
+                let key = self.expression()?;
+                self.consume(&TokenType::Colon, tr(Msg::ExpectColonAfterKey, &[]))?;
+                let value = self.expression()?;
+                entries.push((key, value));
+
+                if !self.expect(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            self.close(
+                &TokenType::RightBrace,
+                &brace,
+                tr(Msg::ExpectBraceAfterEntries, &[]),
+            )?;
+            return Ok(Expr::new_map(brace, entries));
+        }
+
         if self.expect(&[TokenType::LeftParen]) {
+            let paren = self.previous().clone();
             let expr = self.expression()?;
-            self.consume(
+            self.close(
                 &TokenType::RightParen,
-                "Expect ')' after expression.".to_string(),
+                &paren,
+                tr(Msg::ExpectParenAfterExpression, &[]),
             )?;
             return Ok(Expr::new_grouping(Box::new(expr)));
         }
 
-        Err(StrixError::ParserError(
+        if self.expect(&[TokenType::Identifier]) {
+            return self.variable();
+        }
+
+        Err(StrixError::at(
+            ErrorCode::ExpectedExpression,
             self.peek(),
-            "Expected expression.".to_string(),
+            tr(Msg::ExpectedExpression, &[]),
+        ))
+    }
+
+    /// Parses `for name in iterable { body }`, after the `for` and its label, if any.
+    fn for_loop(&mut self, label: Option<Token<'a>>) -> Result<Expr<'a>, StrixError> {
+        let keyword = self.previous().clone();
+        let variable = self.consume(&TokenType::Identifier, tr(Msg::ExpectLoopVariable, &[]))?;
+        self.consume(&TokenType::In, tr(Msg::ExpectInAfterLoopVariable, &[]))?;
+        let iterable = self.expression()?;
+
+        let scope = self.locals.len();
+        let name = self.names.intern(variable.lexeme);
+        self.locals.extend([None, Some(name)]);
+        let label_name = label
+            .as_ref()
+            .map(|label| self.names.intern(&label.lexeme[1..]));
+        self.loops.push(label_name);
+        let body = self.block();
+        self.loops.pop();
+        self.locals.truncate(scope);
+
+        Ok(Expr::new_for(
+            label,
+            keyword,
+            variable,
+            Box::new(iterable),
+            Box::new(body?),
+            true,
         ))
     }
 
-    fn consume(&mut self, token_type: &TokenType, message: String) -> Result<Token, StrixError> {
+    /// Parses `try { body } catch (name) { handler } finally { cleanup }`, after the `try`.
+    /// Either clause may be left out, but not both.
+    fn try_catch(&mut self) -> Result<Expr<'a>, StrixError> {
+        let keyword = self.previous().clone();
+        let body = self.block()?;
+
+        let mut handler = None;
+        if self.expect(&[TokenType::Catch]) {
+            self.consume(&TokenType::LeftParen, tr(Msg::ExpectParenAfterCatch, &[]))?;
+            let variable =
+                self.consume(&TokenType::Identifier, tr(Msg::ExpectCatchVariable, &[]))?;
+            self.consume(
+                &TokenType::RightParen,
+                tr(Msg::ExpectParenAfterCatchVariable, &[]),
+            )?;
+
+            let name = self.names.intern(variable.lexeme);
+            self.locals.push(Some(name));
+            let block = self.block();
+            self.locals.pop();
+            handler = Some((variable, Box::new(block?)));
+        }
+
+        let finally = if self.expect(&[TokenType::Finally]) {
+            let mut cleanup = self.block()?;
+            discard(&mut cleanup);
+            Some(Box::new(cleanup))
+        } else {
+            None
+        };
+
+        if handler.is_none() && finally.is_none() {
+            return Err(StrixError::at(
+                ErrorCode::ExpectedToken,
+                self.peek(),
+                tr(Msg::ExpectCatchOrFinally, &[]),
+            ));
+        }
+        Ok(Expr::new_try(keyword, Box::new(body), handler, finally))
+    }
+
+    /// Parses `break` or `continue`, after the keyword, and resolves the loop it targets: the
+    /// one with its label, or the innermost one.
+    fn loop_control(&mut self) -> Result<Expr<'a>, StrixError> {
+        let keyword = self.previous().clone();
+        if self.loops.is_empty() {
+            return Err(StrixError::at(
+                ErrorCode::LoopControlOutsideLoop,
+                &keyword,
+                tr(Msg::LoopControlOutsideLoop, &[&keyword.lexeme]),
+            ));
+        }
+
+        let label = self
+            .expect(&[TokenType::Label])
+            .then(|| self.previous().clone());
+        let loops = match &label {
+            Some(label) => self.loop_labelled(label)?,
+            None => 0,
+        };
+
+        Ok(match keyword.token_type {
+            TokenType::Break => Expr::new_break(keyword, label, loops),
+            _ => Expr::new_continue(keyword, label, loops),
+        })
+    }
+
+    /// How many loops out from the innermost one the loop with `label` is.
+    fn loop_labelled(&mut self, label: &Token<'a>) -> Result<usize, StrixError> {
+        let name = &label.lexeme[1..];
+        let symbol = self.names.intern(name);
+        if let Some(loops) = self
+            .loops
+            .iter()
+            .rev()
+            .position(|label| *label == Some(symbol))
+        {
+            return Ok(loops);
+        }
+
+        let err = StrixError::at(
+            ErrorCode::UndefinedLabel,
+            label,
+            tr(Msg::UndefinedLabel, &[&name]),
+        );
+        let labels = self
+            .loops
+            .iter()
+            .flatten()
+            .map(|&label| &**self.names.resolve(label));
+        Err(match suggest::did_you_mean(name, labels) {
+            Some(note) => err.with_note(note),
+            None => err,
+        })
+    }
+
+    /// Parses `{ a; b }`, whose value is that of its last expression. An empty block, or one
+    /// ending in `;`, evaluates to nil. The values of the others are unused.
+    fn block(&mut self) -> Result<Expr<'a>, StrixError> {
+        let brace = self.consume(&TokenType::LeftBrace, tr(Msg::ExpectBraceBeforeBlock, &[]))?;
+        let mut expressions = Vec::new();
+
+        while !self.check(&TokenType::RightBrace) {
+            expressions.push(self.expression()?);
+            if !self.expect(&[TokenType::Semicolon]) {
+                break;
+            }
+            if self.check(&TokenType::RightBrace) {
+                expressions.push(Expr::new_literal(Literal::Nil));
+            }
+        }
+
+        self.close(
+            &TokenType::RightBrace,
+            &brace,
+            tr(Msg::ExpectBraceAfterBlock, &[]),
+        )?;
+        if let Some((_, unused)) = expressions.split_last_mut() {
+            unused.iter_mut().for_each(discard);
+        }
+        Ok(Expr::new_block(brace, expressions))
+    }
+
+    /// Resolves a name to the innermost variable in scope with it.
+    fn variable(&mut self) -> Result<Expr<'a>, StrixError> {
+        let name = self.previous().clone();
+        let symbol = self.names.intern(name.lexeme);
+        if let Some(slot) = self.locals.iter().rposition(|local| *local == Some(symbol)) {
+            return Ok(Expr::new_variable(name, slot));
+        }
+
+        // An unknown name is most likely a misspelled keyword or variable.
+        let mut candidates: Vec<&str> = self
+            .locals
+            .iter()
+            .flatten()
+            .map(|&local| &**self.names.resolve(local))
+            .collect();
+        candidates.extend(tokenizer::keywords().map(|keyword| keyword as &str));
+        let err = StrixError::at(
+            ErrorCode::UndefinedVariable,
+            &name,
+            tr(Msg::UndefinedVariable, &[&name.lexeme]),
+        );
+        Err(match suggest::did_you_mean(name.lexeme, candidates) {
+            Some(note) => err.with_note(note),
+            None => err,
+        })
+    }
+
+    fn consume(
+        &mut self,
+        token_type: &TokenType,
+        message: String,
+    ) -> Result<Token<'a>, StrixError> {
         if self.check(token_type) {
-            Ok(self.advance())
+            Ok(self.advance().clone())
         } else {
-            Err(StrixError::ParserError(self.peek(), message))
+            Err(StrixError::at(
+                ErrorCode::ExpectedToken,
+                self.peek(),
+                message,
+            ))
         }
     }
 
+    /// Consumes the delimiter closing `opening`, pointing back at it when it is missing.
+    fn close(
+        &mut self,
+        token_type: &TokenType,
+        opening: &Token<'a>,
+        message: String,
+    ) -> Result<Token<'a>, StrixError> {
+        self.consume(token_type, message).map_err(|err| {
+            err.with_label(opening.span, tr(Msg::MatchDelimiter, &[&opening.lexeme]))
+        })
+    }
+
     #[allow(dead_code)]
     fn synchronize(&mut self) {
         self.advance();
@@ -187,7 +867,137 @@ impl Parser {
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr, StrixError> {
-        self.expression()
+    pub fn parse(&mut self) -> Result<Expr<'a>, StrixError> {
+        self.depth = 0;
+        self.locals.clear();
+        self.loops.clear();
+        let expr = self.expression().and_then(|expr| {
+            if self.is_at_end() {
+                return Ok(expr);
+            }
+            Err(StrixError::at(
+                ErrorCode::ExpectedToken,
+                self.peek(),
+                tr(Msg::ExpectEndOfInput, &[]),
+            ))
+        });
+
+        // Scan the rest of the input, so that an error in it is reported even when the
+        // expression ended before it. Scan errors come first, as the parser only saw an
+        // early end of input.
+        while !self.is_at_end() {
+            self.advance();
+        }
+        match self.scan_error.take() {
+            Some(err) => Err(err),
+            None => expr,
+        }
     }
 }
+
+/// Marks the loops `expr` takes its value from as unused, so that they evaluate to nil rather
+/// than collect a list nobody reads. Those are `expr` itself and what its value is made of:
+/// the last expression of a block, a group's expression, the branches of a conditional, the
+/// right side of `??` and the body and handler of a `try`.
+fn discard(expr: &mut Expr) {
+    match expr {
+        Expr::For { body, collect, .. } => {
+            *collect = false;
+            discard(body);
+        }
+        Expr::Block { expressions, .. } => {
+            if let Some(last) = expressions.last_mut() {
+                discard(last);
+            }
+        }
+        Expr::Grouping { expression } => discard(expression),
+        Expr::Ternary {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            discard(then_branch);
+            discard(else_branch);
+        }
+        Expr::Coalesce { right, .. } => discard(right),
+        Expr::Try { body, handler, .. } => {
+            discard(body);
+            if let Some((_, handler)) = handler {
+                discard(handler);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Splits a `++` or `--` token into its two single-character operators.
+fn split<'a>(token: &Token<'a>) -> (Token<'a>, Token<'a>) {
+    let (token_type, lexeme) = match token.token_type {
+        TokenType::PlusPlus => (TokenType::Plus, "+"),
+        _ => (TokenType::Minus, "-"),
+    };
+    let Span {
+        start,
+        end,
+        line,
+        column,
+    } = token.span;
+
+    (
+        Token::spanned(
+            token_type,
+            lexeme,
+            None,
+            Span::new(start, start + 1, line, column),
+        ),
+        Token::spanned(
+            token_type,
+            lexeme,
+            None,
+            Span::new(start + 1, end.max(start + 1), line, column + 1),
+        ),
+    )
+}
+
+/// The precedence of a bitwise or shift operator, higher binding tighter.
+fn bitwise_precedence(token_type: TokenType) -> Option<u8> {
+    match token_type {
+        TokenType::Pipe => Some(0),
+        TokenType::Caret => Some(1),
+        TokenType::Ampersand => Some(2),
+        TokenType::LessLess | TokenType::GreaterGreater => Some(3),
+        _ => None,
+    }
+}
+
+/// Whether a token of this type can begin an operand.
+fn starts_operand(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Number
+            | TokenType::String
+            | TokenType::Identifier
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::Throw
+            | TokenType::For
+            | TokenType::Try
+            | TokenType::Label
+            | TokenType::Break
+            | TokenType::Continue
+            | TokenType::LeftParen
+            | TokenType::LeftBracket
+            | TokenType::LeftBrace
+            | TokenType::Bang
+            | TokenType::Minus
+            | TokenType::MinusMinus
+            | TokenType::PlusPlus
+    )
+}
+
+/// Reports an assignment or increment of a loop or catch variable.
+fn read_only(code: ErrorCode, operator: &Token, name: &Token) -> StrixError {
+    StrixError::at(code, operator, tr(Msg::ReadOnlyVariable, &[&name.lexeme]))
+        .with_note(tr(Msg::VariablesAreReadOnly, &[]))
+}
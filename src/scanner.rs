@@ -1,38 +1,63 @@
+use std::borrow::Cow;
+
 use crate::{
-    err::StrixError,
+    err::{ErrorCode, Span, StrixError},
+    messages::{Msg, tr},
     tokenizer::{Literal, Token, TokenType, get_keyword_token},
 };
 
-pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+/// Turns source text into tokens one at a time, as an iterator. Scanning ends after the
+/// end-of-file token or the first error.
+pub struct Scanner<'a> {
+    source: &'a str,
+    token: Option<Token<'a>>,
+    done: bool,
     start: usize,
     current: usize,
     line: usize,
+    /// The column of the character at `current`, counted in characters from 1.
+    column: usize,
+    start_line: usize,
+    start_column: usize,
 }
 
-impl Scanner {
-    pub fn from(source: String) -> Self {
+impl<'a> Scanner<'a> {
+    pub fn from(source: &'a str) -> Self {
         Self {
             source,
-            tokens: vec![],
+            token: None,
+            done: false,
             current: 0,
             line: 1,
             start: 0,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            if let Err(err) = self.scan_token() {
-                eprintln!("{:#?}", err)
-            }
-        }
+    /// Scans the whole source. Tokens borrow their lexemes from it rather than copying them.
+    /// The parser pulls tokens one at a time instead, so only the tests need the whole list.
+    #[cfg(test)]
+    pub fn scan_tokens(self) -> Result<Vec<Token<'a>>, StrixError> {
+        self.collect()
+    }
+
+    /// Marks the start of the next lexeme at the current position.
+    fn begin(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+    }
 
-        self.tokens
-            .push(Token::new(TokenType::Eof, String::new(), None, self.line));
-        &self.tokens
+    /// The span of the lexeme being scanned.
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
     }
 
     fn is_at_end(&self) -> bool {
@@ -46,12 +71,69 @@ impl Scanner {
                 ')' => self.add_token(TokenType::RightParen, None),
                 '{' => self.add_token(TokenType::LeftBrace, None),
                 '}' => self.add_token(TokenType::RightBrace, None),
+                '[' => self.add_token(TokenType::LeftBracket, None),
+                ']' => self.add_token(TokenType::RightBracket, None),
                 ',' => self.add_token(TokenType::Comma, None),
-                '.' => self.add_token(TokenType::Dot, None),
-                '-' => self.add_token(TokenType::Minus, None),
-                '+' => self.add_token(TokenType::Plus, None),
+                '.' => {
+                    if self.expect('.') {
+                        if self.expect('=') {
+                            self.add_token(TokenType::DotDotEqual, None);
+                        } else {
+                            self.add_token(TokenType::DotDot, None);
+                        }
+                    } else {
+                        self.add_token(TokenType::Dot, None);
+                    }
+                }
                 ';' => self.add_token(TokenType::Semicolon, None),
-                '*' => self.add_token(TokenType::Star, None),
+                ':' => self.add_token(TokenType::Colon, None),
+                '?' => {
+                    if self.expect('?') {
+                        self.add_token(TokenType::QuestionQuestion, None);
+                    } else if self.expect('.') {
+                        self.add_token(TokenType::QuestionDot, None);
+                    } else {
+                        self.add_token(TokenType::Question, None);
+                    }
+                }
+                '-' => {
+                    if self.expect('-') {
+                        self.add_token(TokenType::MinusMinus, None);
+                    } else if self.expect('=') {
+                        self.add_token(TokenType::MinusEqual, None);
+                    } else {
+                        self.add_token(TokenType::Minus, None);
+                    }
+                }
+                '+' => {
+                    if self.expect('+') {
+                        self.add_token(TokenType::PlusPlus, None);
+                    } else if self.expect('=') {
+                        self.add_token(TokenType::PlusEqual, None);
+                    } else {
+                        self.add_token(TokenType::Plus, None);
+                    }
+                }
+                '*' => {
+                    if self.expect('=') {
+                        self.add_token(TokenType::StarEqual, None);
+                    } else {
+                        self.add_token(TokenType::Star, None);
+                    }
+                }
+                '%' | '&' | '|' | '^' => {
+                    let token_type = match (c, self.expect('=')) {
+                        ('%', true) => TokenType::PercentEqual,
+                        ('%', false) => TokenType::Percent,
+                        ('&', true) => TokenType::AmpersandEqual,
+                        ('&', false) => TokenType::Ampersand,
+                        ('|', true) => TokenType::PipeEqual,
+                        ('|', false) => TokenType::Pipe,
+                        (_, true) => TokenType::CaretEqual,
+                        (_, false) => TokenType::Caret,
+                    };
+                    self.add_token(token_type, None);
+                }
                 '!' => {
                     if self.expect('=') {
                         self.add_token(TokenType::BangEqual, None);
@@ -69,6 +151,12 @@ impl Scanner {
                 '<' => {
                     if self.expect('=') {
                         self.add_token(TokenType::LessEqual, None);
+                    } else if self.expect('<') {
+                        if self.expect('=') {
+                            self.add_token(TokenType::LessLessEqual, None);
+                        } else {
+                            self.add_token(TokenType::LessLess, None);
+                        }
                     } else {
                         self.add_token(TokenType::Less, None);
                     }
@@ -76,6 +164,12 @@ impl Scanner {
                 '>' => {
                     if self.expect('=') {
                         self.add_token(TokenType::GreaterEqual, None);
+                    } else if self.expect('>') {
+                        if self.expect('=') {
+                            self.add_token(TokenType::GreaterGreaterEqual, None);
+                        } else {
+                            self.add_token(TokenType::GreaterGreater, None);
+                        }
                     } else {
                         self.add_token(TokenType::Greater, None);
                     }
@@ -85,6 +179,8 @@ impl Scanner {
                         while self.peek() != '\n' && !self.is_at_end() {
                             self.advance();
                         }
+                    } else if self.expect('=') {
+                        self.add_token(TokenType::SlashEqual, None);
                     } else {
                         self.add_token(TokenType::Slash, None);
                     }
@@ -92,28 +188,23 @@ impl Scanner {
 
                 ' ' | '\r' | '\t' => { /* Ignore whitespaces */ }
 
-                '\n' => {
-                    self.line += 1;
-                }
+                '\n' => self.new_line(),
 
                 '"' => return self.string(),
 
-                'o' => {
-                    if self.expect('r') {
-                        self.add_token(TokenType::Or, None);
-                    }
-                }
+                '\'' if self.peek().is_alphabetic() => self.label(),
 
                 _ => {
-                    if c.is_numeric() {
+                    if c.is_ascii_digit() {
                         self.number();
                     } else if c.is_alphabetic() {
                         self.identifier();
                     } else {
-                        return Err(StrixError::ScannerError(
-                            self.line,
-                            "Unexpected character.".to_string(),
-                        ));
+                        return Err(StrixError::new(
+                            ErrorCode::UnexpectedCharacter,
+                            tr(Msg::UnexpectedCharacter, &[&c]),
+                        )
+                        .with_span(self.span()));
                     }
                 }
             }
@@ -123,79 +214,73 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> Option<char> {
-        if self.current >= self.source.len() {
-            return None;
-        }
-
-        let char_to_return = self.source.chars().nth(self.current);
+        let c = self.source[self.current..].chars().next()?;
 
-        self.current += 1;
+        // Positions are byte offsets, so multi-byte characters advance by their full width.
+        self.current += c.len_utf8();
+        self.column += 1;
 
-        char_to_return
+        Some(c)
     }
 
-    fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
+    fn add_token(&mut self, token_type: TokenType, literal: Option<Literal<'a>>) {
         let lexeme = &self.source[self.start..self.current];
-        self.tokens.push(Token::new(
-            token_type,
-            lexeme.to_string(),
-            literal,
-            self.line,
-        ));
+        self.token = Some(Token::spanned(token_type, lexeme, literal, self.span()));
     }
 
     fn expect(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
         };
-        if self.source.chars().nth(self.current) != Some(expected) {
+        if self.peek() != expected {
             return false;
         };
 
-        self.current += 1;
+        self.current += expected.len_utf8();
+        self.column += 1;
         true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-
-        self.source.chars().nth(self.current).unwrap_or('\0')
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn string(&mut self) -> Result<(), StrixError> {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == Some('\n') {
+                self.new_line();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            return Err(StrixError::ScannerError(
-                self.line,
-                "Unterminated string.".to_string(),
-            ));
+            return Err(StrixError::new(
+                ErrorCode::UnterminatedString,
+                tr(Msg::UnterminatedString, &[]),
+            )
+            .with_span(self.span())
+            .with_note(tr(Msg::CloseString, &[])));
         }
 
         self.advance();
 
         let value = &self.source[self.start + 1..self.current - 1];
-        self.add_token(TokenType::String, Some(Literal::String(value.to_string())));
+        self.add_token(
+            TokenType::String,
+            Some(Literal::String(Cow::Borrowed(value))),
+        );
 
         Ok(())
     }
 
     fn number(&mut self) {
-        while self.peek().is_numeric() {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_numeric() {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
-            while self.peek().is_numeric() {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -206,11 +291,18 @@ impl Scanner {
     }
 
     fn peek_next(&mut self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
+    }
+
+    /// Scans a loop label such as `'outer`, after its quote.
+    fn label(&mut self) {
+        while self.peek().is_alphanumeric() {
+            self.advance();
         }
 
-        self.source.chars().nth(self.current + 1).unwrap_or('\0')
+        self.add_token(TokenType::Label, None);
     }
 
     fn identifier(&mut self) {
@@ -226,3 +318,29 @@ impl Scanner {
         }
     }
 }
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token<'a>, StrixError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // Whitespace and comments produce no token, so keep scanning until something does.
+        while !self.is_at_end() {
+            self.begin();
+            if let Err(err) = self.scan_token() {
+                self.done = true;
+                return Some(Err(err));
+            }
+            if let Some(token) = self.token.take() {
+                return Some(Ok(token));
+            }
+        }
+
+        self.begin();
+        self.done = true;
+        Some(Ok(Token::spanned(TokenType::Eof, "", None, self.span())))
+    }
+}
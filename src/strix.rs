@@ -1,72 +1,424 @@
 use std::{
     fs,
     io::{self, Write},
+    path::Path,
     process,
 };
 
-use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+use crate::{
+    bytecode,
+    chunk::Chunk,
+    compiler::Compiler,
+    disasm,
+    err::{ErrorCode, FileId, StrixError},
+    expr::Expr,
+    gc::GcStats,
+    interpreter::{DEFAULT_MAX_CALL_DEPTH, Interpreter},
+    messages::{self, Locale, Msg, tr},
+    optimizer::{self, Optimizer},
+    parser::{DEFAULT_MAX_DEPTH, Parser},
+    scanner::Scanner,
+    suggest,
+    value::Value,
+    vm::Vm,
+};
+
+/// The REPL's dot-commands.
+const COMMANDS: [&str; 2] = [".clear", ".exit"];
+
+/// How errors are reported on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Readable text, with at most `max_frames` frames of each stack trace.
+    Human,
+    /// One JSON object per line, with the full stack trace.
+    Json,
+}
+
+/// What runs a parsed script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Walks the syntax tree directly.
+    Tree,
+    /// Compiles the syntax tree to bytecode and runs it on a stack machine.
+    Vm,
+}
+
+/// How many stack frames an error report shows unless configured otherwise.
+pub const DEFAULT_MAX_FRAMES: usize = 16;
 
 pub struct Strix {
     had_error: bool,
+    error_format: ErrorFormat,
+    engine: Engine,
+    trace_exec: bool,
+    opt_level: u8,
+    /// Names of the sources run so far, indexed by `FileId`.
+    files: Vec<String>,
+    max_frames: usize,
+    max_call_depth: usize,
+    max_nesting: usize,
+    max_heap: Option<usize>,
+    gc_stress: bool,
+    print_gc_stats: bool,
+    /// What the garbage collector did during the last run.
+    gc_stats: GcStats,
+    /// The language of the messages it creates.
+    locale: Locale,
 }
 
 impl Strix {
     pub fn new() -> Self {
-        Self { had_error: false }
+        Self {
+            had_error: false,
+            error_format: ErrorFormat::Human,
+            engine: Engine::Tree,
+            trace_exec: false,
+            opt_level: optimizer::DEFAULT_LEVEL,
+            files: vec![],
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_nesting: DEFAULT_MAX_DEPTH,
+            max_heap: None,
+            gc_stress: false,
+            print_gc_stats: false,
+            gc_stats: GcStats::default(),
+            locale: Locale::En,
+        }
     }
 
-    fn run(&mut self, source: String) {
-        let mut scanner = Scanner::from(source);
-        let tokens = scanner.scan_tokens();
+    /// Sets the language of diagnostics and other messages from this instance.
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
 
-        let mut parser = Parser::from(tokens.clone());
-        let expr = parser.parse().unwrap();
+    pub fn set_error_format(&mut self, error_format: ErrorFormat) {
+        self.error_format = error_format;
+    }
 
-        let mut interpreter = Interpreter::new();
-        if let Ok(result) = interpreter.interpret(&expr) {
-            println!("{result}")
-        }
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
     }
 
-    pub fn run_prompt(&mut self) {
-        loop {
-            println!("Strix Language REPL | v1.0");
-            print!("> ");
-            io::stdout().flush().unwrap();
+    /// Prints each instruction and the stack as the VM runs them.
+    pub fn set_trace_exec(&mut self, trace_exec: bool) {
+        self.trace_exec = trace_exec;
+    }
+
+    /// Sets how much scripts are optimized before they run or are compiled, from 0 for not at
+    /// all to `optimizer::MAX_LEVEL`.
+    pub fn set_opt_level(&mut self, opt_level: u8) {
+        self.opt_level = opt_level;
+    }
 
-            let mut input = String::new();
-            io::stdin()
-                .read_line(&mut input)
-                .expect("Unable to read line");
+    /// Limits how many stack frames are shown when reporting a runtime error.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
 
-            let line = input.trim();
+    /// Limits how many calls may be active at once before a stack overflow error. Without
+    /// script functions at most two are, so see `DEFAULT_MAX_CALL_DEPTH` before relying on it.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
 
-            if line == "Strix::clear()" || line == ".clear" {
-                clearscreen::clear().unwrap();
-                continue;
+    /// Limits how deeply expressions may nest before a parse error.
+    pub fn set_max_nesting(&mut self, max_nesting: usize) {
+        self.max_nesting = max_nesting;
+    }
+
+    /// Limits how many bytes of lists and maps a script may keep reachable before an out of
+    /// memory error. There is no limit by default.
+    pub fn set_max_heap(&mut self, max_heap: Option<usize>) {
+        self.max_heap = max_heap;
+    }
+
+    /// Runs the garbage collector at every allocation, for testing.
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.gc_stress = gc_stress;
+    }
+
+    /// Prints what the garbage collector did after each run.
+    pub fn set_print_gc_stats(&mut self, print_gc_stats: bool) {
+        self.print_gc_stats = print_gc_stats;
+    }
+
+    /// What the garbage collector did during the last run, as `--gc-stats` prints it.
+    pub fn gc_stats(&self) -> GcStats {
+        self.gc_stats
+    }
+
+    /// Returns the id of a source file, registering it the first time it is seen.
+    fn file_id(&mut self, name: &str) -> FileId {
+        match self.files.iter().position(|file| file == name) {
+            Some(i) => FileId(i),
+            None => {
+                self.files.push(name.to_string());
+                FileId(self.files.len() - 1)
             }
+        }
+    }
+
+    fn report(&mut self, err: &StrixError) {
+        let file = err.file.and_then(|FileId(i)| self.files.get(i));
+        match self.error_format {
+            ErrorFormat::Json => eprintln!("{}", err.render_json(file.map(String::as_str))),
+            ErrorFormat::Human => {
+                let rendered = err.render(self.max_frames);
+                match file {
+                    Some(file) => eprintln!("{file}: {rendered}"),
+                    None => eprintln!("{rendered}"),
+                }
+            }
+        }
+
+        self.had_error = true;
+    }
+
+    /// Runs a script on the selected engine and returns its value, leaving any error for the
+    /// host to handle.
+    pub fn eval(&mut self, source: &str) -> Result<Value, StrixError> {
+        messages::with_locale(self.locale, || {
+            let id = self.file_id("<script>");
+            self.evaluate(source, "<script>")
+                .map_err(|err| err.with_file(id))
+        })
+    }
+
+    /// Runs a script file like `eval`, loading it as bytecode if it has the `.strixc`
+    /// extension or starts with the bytecode header. Bytecode always runs on the VM.
+    pub fn eval_file(&mut self, path: &str) -> Result<Value, StrixError> {
+        messages::with_locale(self.locale, || {
+            let bytes = read(path)?;
+            let result = if is_bytecode(path, &bytes) {
+                bytecode::read(&bytes, path).and_then(|chunk| self.execute_chunk(&chunk, path))
+            } else {
+                let source = decode(path, bytes)?;
+                self.evaluate(&source, path)
+            };
+            let id = self.file_id(path);
+            result.map_err(|err| err.with_file(id))
+        })
+    }
+
+    fn evaluate(&mut self, source: &str, file: &str) -> Result<Value, StrixError> {
+        let expr = self.parse(source)?;
+        self.execute(&expr, file)
+    }
+
+    fn parse<'a>(&self, source: &'a str) -> Result<Expr<'a>, StrixError> {
+        let mut parser = Parser::new(Scanner::from(source));
+        parser.set_max_depth(self.max_nesting);
+        let expr = parser.parse()?;
+
+        let mut optimizer = Optimizer::new();
+        optimizer.set_level(self.opt_level);
+        Ok(optimizer.optimize(&expr))
+    }
 
-            if line == "Strix::exit()" || line == ".exit" {
-                println!("Bye!");
-                process::exit(0);
+    fn execute(&mut self, expr: &Expr, file: &str) -> Result<Value, StrixError> {
+        match self.engine {
+            Engine::Tree => {
+                let mut interpreter = Interpreter::with_file(file.to_string());
+                interpreter.set_max_call_depth(self.max_call_depth);
+                interpreter.set_max_heap(self.max_heap);
+                interpreter.set_gc_stress(self.gc_stress);
+                let result = interpreter.interpret(expr);
+                self.record_gc_stats(interpreter.gc_stats());
+                result
             }
+            Engine::Vm => self.execute_chunk(&Compiler::new().compile(expr)?, file),
+        }
+    }
 
-            self.run(String::from(line));
-            self.had_error = false;
+    fn execute_chunk(&mut self, chunk: &Chunk, file: &str) -> Result<Value, StrixError> {
+        let mut vm = Vm::with_file(file.to_string());
+        vm.set_max_call_depth(self.max_call_depth);
+        vm.set_trace_exec(self.trace_exec);
+        vm.set_max_heap(self.max_heap);
+        vm.set_gc_stress(self.gc_stress);
+        let result = vm.run(chunk);
+        self.record_gc_stats(vm.gc_stats());
+        result
+    }
+
+    fn record_gc_stats(&mut self, gc_stats: GcStats) {
+        self.gc_stats = gc_stats;
+        if self.print_gc_stats {
+            eprintln!("{}", self.gc_stats());
         }
     }
 
-    pub fn run_file(&mut self, path: &String) {
-        match fs::read(path) {
-            Ok(bytes) => {
-                self.run(String::from_utf8(bytes).expect("Invalid UTF-8 byte found"));
+    pub fn run_prompt(&mut self) {
+        messages::with_locale(self.locale, || {
+            loop {
+                println!("Strix Language REPL | v1.0");
+                print!("> ");
+                // A prompt that fails to flush is only cosmetic, so the error is ignored.
+                let _ = io::stdout().flush();
+
+                let mut input = String::new();
+                match io::stdin().read_line(&mut input) {
+                    Ok(0) => return,
+                    Ok(_) => {}
+                    Err(err) => {
+                        return self.report(&StrixError::new(
+                            ErrorCode::UnreadableFile,
+                            tr(Msg::UnreadableFile, &[&"<stdin>", &err]),
+                        ));
+                    }
+                }
+
+                let line = input.trim();
+
+                if line == "Strix::clear()" || line == ".clear" {
+                    if let Err(err) = clearscreen::clear() {
+                        eprintln!("{}", tr(Msg::ClearScreenFailed, &[&err]));
+                    }
+                    continue;
+                }
+
+                if line == "Strix::exit()" || line == ".exit" {
+                    println!("{}", tr(Msg::Bye, &[]));
+                    process::exit(0);
+                }
+
+                // No expression starts with a dot, so anything else of this form is a mistyped command.
+                if line.starts_with('.') && line.len() > 1 {
+                    let message = tr(Msg::UnknownCommand, &[&line]);
+                    match suggest::did_you_mean(line, COMMANDS) {
+                        Some(note) => eprintln!("{message} {note}"),
+                        None => eprintln!("{message}"),
+                    }
+                    continue;
+                }
 
-                if self.had_error {
-                    process::exit(65);
+                match self.eval(line) {
+                    Ok(result) => println!("{result}"),
+                    Err(err) => self.report(&err),
                 }
+                self.had_error = false;
             }
+        })
+    }
 
-            Err(err) => panic!("{err:#?}"),
-        };
+    /// Runs a script file and prints its value, or reports its error and exits.
+    pub fn run_file(&mut self, path: &str) {
+        messages::with_locale(self.locale, || match self.eval_file(path) {
+            Ok(result) => println!("{result}"),
+            Err(err) => {
+                self.report(&err);
+                process::exit(if err.code == ErrorCode::UnreadableFile {
+                    74
+                } else {
+                    65
+                });
+            }
+        })
     }
+
+    /// Compiles a script file to bytecode and writes it to `output`, or next to the script
+    /// with the `.strixc` extension.
+    pub fn compile_file(&mut self, path: &str, output: Option<&str>) {
+        messages::with_locale(self.locale, || {
+            let bytes = self.read_file(path);
+            let source = self.read_source(path, bytes);
+            let chunk = self
+                .parse(&source)
+                .and_then(|expr| Compiler::new().compile(&expr));
+            let chunk = self.or_exit(chunk, path);
+
+            let output = match output {
+                Some(output) => output.to_string(),
+                None => Path::new(path)
+                    .with_extension(bytecode::EXTENSION)
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            if let Err(err) = fs::write(&output, bytecode::write(&chunk)) {
+                self.report(&StrixError::new(
+                    ErrorCode::UnwritableFile,
+                    tr(Msg::UnwritableFile, &[&output, &err]),
+                ));
+                process::exit(74);
+            }
+        })
+    }
+
+    /// Prints the bytecode of a script file, compiling it first unless it is already compiled.
+    pub fn disassemble_file(&mut self, path: &str) {
+        messages::with_locale(self.locale, || {
+            let bytes = self.read_file(path);
+
+            let chunk = if is_bytecode(path, &bytes) {
+                bytecode::read(&bytes, path)
+            } else {
+                let source = self.read_source(path, bytes);
+                self.parse(&source)
+                    .and_then(|expr| Compiler::new().compile(&expr))
+            };
+            let chunk = self.or_exit(chunk, path);
+
+            print!("{}", disasm::disassemble(&chunk, "<script>"));
+        })
+    }
+
+    /// Unwraps the result of loading `path`, reporting its error and exiting if it failed.
+    fn or_exit<T>(&mut self, result: Result<T, StrixError>, path: &str) -> T {
+        match result {
+            Ok(value) => value,
+            Err(err) => {
+                let id = self.file_id(path);
+                self.report(&err.with_file(id));
+                process::exit(65);
+            }
+        }
+    }
+
+    fn read_file(&mut self, path: &str) -> Vec<u8> {
+        match read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.report(&err);
+                process::exit(74);
+            }
+        }
+    }
+
+    fn read_source(&mut self, path: &str, bytes: Vec<u8>) -> String {
+        match decode(path, bytes) {
+            Ok(source) => source,
+            Err(err) => {
+                self.report(&err);
+                process::exit(65);
+            }
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, StrixError> {
+    fs::read(path).map_err(|err| {
+        StrixError::new(
+            ErrorCode::UnreadableFile,
+            tr(Msg::UnreadableFile, &[&path, &err]),
+        )
+    })
+}
+
+/// Reads a script's source from the bytes of its file, which must be UTF-8.
+fn decode(path: &str, bytes: Vec<u8>) -> Result<String, StrixError> {
+    String::from_utf8(bytes).map_err(|err| {
+        let offset = err.utf8_error().valid_up_to();
+        StrixError::new(
+            ErrorCode::InvalidEncoding,
+            tr(Msg::InvalidEncoding, &[&path, &offset]),
+        )
+    })
+}
+
+fn is_bytecode(path: &str, bytes: &[u8]) -> bool {
+    bytes.starts_with(&bytecode::MAGIC)
+        || Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension == bytecode::EXTENSION)
 }
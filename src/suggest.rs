@@ -0,0 +1,67 @@
+use crate::messages::{Msg, tr};
+
+/// Finds the candidate closest to a misspelled `name`, if any is close enough to be a
+/// plausible typo.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // At most one edit for every three characters of the longer of the two names.
+    let len = name.chars().count();
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance * 3 <= len.max(candidate.chars().count()))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Builds the help note suggesting `name`'s closest candidate.
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    closest(name, candidates).map(|candidate| tr(Msg::DidYouMean, &[&candidate]))
+}
+
+/// How far `name` is from `candidate`. A name that starts with the candidate, give or take a
+/// typo, is one edit further than its start is, so that `pushAll` finds `push`. Candidates
+/// shorter than four characters only match whole names, as too many names start with them.
+fn distance(name: &str, candidate: &str) -> usize {
+    let whole = edit_distance(name, candidate);
+    let len = candidate.chars().count();
+    if len < 4 || name.chars().count() <= len {
+        return whole;
+    }
+
+    let start: String = name.chars().take(len).collect();
+    whole.min(edit_distance(&start, candidate) + 1)
+}
+
+/// Counts the insertions, deletions, substitutions and swaps of adjacent characters needed
+/// to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Three rows of the distance table: two rows back, the previous row and the current one.
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
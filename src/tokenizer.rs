@@ -1,37 +1,52 @@
-use std::fmt;
+use std::{borrow::Cow, fmt};
 
 use phf::phf_map;
 
+use crate::err::Span;
+
 static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
-    "and"    => TokenType::And,
-    "class"  => TokenType::Class,
-    "else"   => TokenType::Else,
-    "false"  => TokenType::False,
-    "for"    => TokenType::For,
-    "fun"    => TokenType::Fun,
-    "if"     => TokenType::If,
-    "nil"    => TokenType::Nil,
-    "or"     => TokenType::Or,
-    "print"  => TokenType::Print,
-    "return" => TokenType::Return,
-    "super"  => TokenType::Super,
-    "this"   => TokenType::This,
-    "true"   => TokenType::True,
-    "var"    => TokenType::Var,
-    "while"  => TokenType::While,
+    "and"      => TokenType::And,
+    "break"    => TokenType::Break,
+    "catch"    => TokenType::Catch,
+    "class"    => TokenType::Class,
+    "continue" => TokenType::Continue,
+    "else"     => TokenType::Else,
+    "false"    => TokenType::False,
+    "finally"  => TokenType::Finally,
+    "for"      => TokenType::For,
+    "fun"      => TokenType::Fun,
+    "if"       => TokenType::If,
+    "in"       => TokenType::In,
+    "nil"      => TokenType::Nil,
+    "or"       => TokenType::Or,
+    "print"    => TokenType::Print,
+    "return"   => TokenType::Return,
+    "super"    => TokenType::Super,
+    "this"     => TokenType::This,
+    "throw"    => TokenType::Throw,
+    "true"     => TokenType::True,
+    "try"      => TokenType::Try,
+    "var"      => TokenType::Var,
+    "while"    => TokenType::While,
 };
 
+pub fn keywords() -> impl Iterator<Item = &'static str> {
+    KEYWORDS.keys().copied()
+}
+
 pub fn get_keyword_token(keyword: &str) -> Option<TokenType> {
     KEYWORDS.get(keyword).cloned()
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -39,6 +54,11 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Colon,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
 
     // One or two character tokens.
     Bang,
@@ -49,27 +69,56 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    Question,
+    QuestionQuestion,
+    QuestionDot,
+    DotDot,
+    DotDotEqual,
+
+    // Compound assignment tokens.
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    LessLessEqual,
+    GreaterGreaterEqual,
+    PlusPlus,
+    MinusMinus,
 
     // Literals.
     Identifier,
     String,
     Number,
+    Label,
 
     // Keywords.
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -82,15 +131,16 @@ impl fmt::Display for TokenType {
     }
 }
 
+/// A literal value. Strings scanned from source borrow their text from it.
 #[derive(Debug, Clone, PartialEq)]
-pub enum Literal {
-    String(String),
+pub enum Literal<'a> {
+    String(Cow<'a, str>),
     Number(f64),
     Bool(bool),
     Nil,
 }
 
-impl fmt::Display for Literal {
+impl fmt::Display for Literal<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Literal::String(s) => write!(f, "{}", s),
@@ -101,31 +151,43 @@ impl fmt::Display for Literal {
     }
 }
 
+/// A token scanned from source. Its lexeme is a slice of the source rather than a copy, so
+/// scanning and parsing do not allocate per token.
 #[derive(Debug, Clone)]
-pub struct Token {
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: String,
-    pub literal: Option<Literal>,
-    pub line: usize,
+    pub lexeme: &'a str,
+    pub literal: Option<Literal<'a>>,
+    pub span: Span,
 }
 
-impl Token {
+impl<'a> Token<'a> {
+    /// Creates a token that is only known to be on `line`, such as one built by hand.
     pub fn new(
         token_type: TokenType,
-        lexeme: String,
-        literal: Option<Literal>,
+        lexeme: &'a str,
+        literal: Option<Literal<'a>>,
         line: usize,
+    ) -> Self {
+        Self::spanned(token_type, lexeme, literal, Span::new(0, 0, line, 1))
+    }
+
+    pub fn spanned(
+        token_type: TokenType,
+        lexeme: &'a str,
+        literal: Option<Literal<'a>>,
+        span: Span,
     ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
-            line,
+            span,
         }
     }
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let literal_str = match &self.literal {
             Some(lit) => lit.to_string(),
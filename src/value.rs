@@ -0,0 +1,546 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use crate::{err::StrixError, tokenizer::Literal};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// Strings are immutable, so copies share one allocation.
+    String(Rc<str>),
+    Number(f64),
+    Bool(bool),
+    Nil,
+    List(Rc<RefCell<List>>),
+    Map(Rc<RefCell<Map>>),
+    Range(Range),
+    Iterator(Iter),
+    Error(Caught),
+}
+
+impl Value {
+    pub fn new_list(elements: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(List(elements))))
+    }
+
+    pub fn new_map(map: Map) -> Self {
+        Self::Map(Rc::new(RefCell::new(map)))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Range(_) => "range",
+            Value::Iterator(_) => "iterator",
+            Value::Error(_) => "error",
+        }
+    }
+
+    /// Formats the value as it appears nested inside a collection, where strings are quoted.
+    fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<Literal<'_>> for Value {
+    fn from(literal: Literal<'_>) -> Self {
+        match literal {
+            Literal::String(s) => Value::String(Rc::from(s.as_ref())),
+            Literal::Number(n) => Value::Number(n),
+            Literal::Bool(b) => Value::Bool(b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "nil"),
+            Value::List(_) | Value::Map(_) => fmt_collection(self, f),
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Iterator(_) => write!(f, "<iterator>"),
+            Value::Error(caught) => {
+                let err = caught.error();
+                write!(f, "<error {}: {}>", err.code, err.message)
+            }
+        }
+    }
+}
+
+/// A list or map being written, with how many of its elements have been.
+enum Open {
+    List(Rc<RefCell<List>>, usize),
+    Map(Rc<RefCell<Map>>, usize),
+}
+
+impl Open {
+    fn address(&self) -> usize {
+        match self {
+            Open::List(list, _) => Rc::as_ptr(list) as usize,
+            Open::Map(map, _) => Rc::as_ptr(map) as usize,
+        }
+    }
+}
+
+/// Writes a list or map without recursing, so that deeply nested ones cannot overflow the
+/// native stack. A list or map inside itself is written as `[...]` or `{...}`.
+fn fmt_collection(value: &Value, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut open: Vec<Open> = vec![];
+    let mut addresses = HashSet::new();
+    let mut next = Some(value.clone());
+
+    loop {
+        match next.take() {
+            Some(Value::List(list)) => {
+                if !addresses.insert(Rc::as_ptr(&list) as usize) {
+                    write!(f, "[...]")?;
+                } else {
+                    write!(f, "[")?;
+                    open.push(Open::List(list, 0));
+                }
+            }
+            Some(Value::Map(map)) => {
+                if !addresses.insert(Rc::as_ptr(&map) as usize) {
+                    write!(f, "{{...}}")?;
+                } else {
+                    write!(f, "{{")?;
+                    open.push(Open::Map(map, 0));
+                }
+            }
+            Some(value) => value.fmt_nested(f)?,
+            None => {}
+        }
+
+        match open.last_mut() {
+            Some(Open::List(list, i)) => {
+                let element = list.borrow().get(*i).cloned();
+                let Some(element) = element else {
+                    addresses.remove(&open.pop().unwrap().address());
+                    write!(f, "]")?;
+                    continue;
+                };
+                if *i > 0 {
+                    write!(f, ", ")?;
+                }
+                *i += 1;
+                next = Some(element);
+            }
+            Some(Open::Map(map, i)) => {
+                let entry = map.borrow().entries.get(*i).cloned();
+                let Some((key, value)) = entry else {
+                    addresses.remove(&open.pop().unwrap().address());
+                    write!(f, "}}")?;
+                    continue;
+                };
+                if *i > 0 {
+                    write!(f, ", ")?;
+                }
+                *i += 1;
+                key.fmt_nested(f)?;
+                write!(f, ": ")?;
+                next = Some(value);
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Lists and maps are equal when their elements are, and compared without recursing, so that
+/// deeply nested ones cannot overflow the native stack. A pair of lists or maps met again
+/// while comparing them counts as equal, so that ones containing themselves compare by shape
+/// rather than forever.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self.clone(), other.clone())];
+        let mut compared = HashSet::new();
+
+        while let Some(pair) = pending.pop() {
+            match pair {
+                (Value::List(a), Value::List(b)) => {
+                    let addresses = (Rc::as_ptr(&a) as usize, Rc::as_ptr(&b) as usize);
+                    if Rc::ptr_eq(&a, &b) || !compared.insert(addresses) {
+                        continue;
+                    }
+                    let (a, b) = (a.borrow(), b.borrow());
+                    if a.len() != b.len() {
+                        return false;
+                    }
+                    pending.extend(a.iter().cloned().zip(b.iter().cloned()));
+                }
+                (Value::Map(a), Value::Map(b)) => {
+                    let addresses = (Rc::as_ptr(&a) as usize, Rc::as_ptr(&b) as usize);
+                    if Rc::ptr_eq(&a, &b) || !compared.insert(addresses) {
+                        continue;
+                    }
+                    let (a, b) = (a.borrow(), b.borrow());
+                    if a.len() != b.len() {
+                        return false;
+                    }
+                    for (key, &i) in &a.indices {
+                        let Some(value) = b.get(key) else {
+                            return false;
+                        };
+                        pending.push((a.entries[i].1.clone(), value.clone()));
+                    }
+                }
+                (Value::String(a), Value::String(b)) if a == b => {}
+                (Value::Number(a), Value::Number(b)) if a == b => {}
+                (Value::Bool(a), Value::Bool(b)) if a == b => {}
+                (Value::Nil, Value::Nil) => {}
+                (Value::Range(a), Value::Range(b)) if a == b => {}
+                (Value::Iterator(a), Value::Iterator(b)) if a == b => {}
+                (Value::Error(a), Value::Error(b)) if a == b => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// The elements of a list, which it derefs to.
+#[derive(Debug, Clone, Default)]
+pub struct List(Vec<Value>);
+
+impl Deref for List {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Vec<Value> {
+        &self.0
+    }
+}
+
+impl DerefMut for List {
+    fn deref_mut(&mut self) -> &mut Vec<Value> {
+        &mut self.0
+    }
+}
+
+impl Drop for List {
+    fn drop(&mut self) {
+        dismantle(mem::take(&mut self.0));
+    }
+}
+
+/// Drops values one at a time, first moving out the contents of each list, map or iterator
+/// that nothing else refers to, so that freeing deeply nested ones cannot overflow the native
+/// stack the way dropping them recursively would.
+fn dismantle(mut pending: Vec<Value>) {
+    while let Some(value) = pending.pop() {
+        match &value {
+            Value::List(list) if Rc::strong_count(list) == 1 => {
+                pending.append(&mut list.borrow_mut().0);
+            }
+            Value::Map(map) if Rc::strong_count(map) == 1 => {
+                let mut map = map.borrow_mut();
+                map.indices.clear();
+                pending.extend(mem::take(&mut map.entries).into_iter().map(|(_, v)| v));
+            }
+            Value::Iterator(iter) if Rc::strong_count(&iter.0) == 1 => {
+                let done = Cursor::Range(Range::new(0, 0, false));
+                match mem::replace(&mut *iter.0.borrow_mut(), done) {
+                    Cursor::List(list, _) => pending.push(Value::List(list)),
+                    Cursor::Keys(map, _) => pending.push(Value::Map(map)),
+                    Cursor::Chars(..) | Cursor::Range(_) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A lazy sequence of integers from `start` up to `end`, stepping by one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Self {
+        Self {
+            start,
+            end,
+            inclusive,
+        }
+    }
+
+    /// The exclusive upper bound of the range.
+    fn stop(&self) -> i64 {
+        if self.inclusive {
+            self.end.saturating_add(1)
+        } else {
+            self.end
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.stop().saturating_sub(self.start).max(0) as u64
+    }
+
+    pub fn contains(&self, n: i64) -> bool {
+        self.start <= n && n < self.stop()
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, operator, self.end)
+    }
+}
+
+/// A position in a list, the keys of a map, the characters of a string or a range, advanced
+/// by `next`. Copies of an iterator share its position, and two iterators are equal only when
+/// they are the same one.
+#[derive(Debug, Clone)]
+pub struct Iter(Rc<RefCell<Cursor>>);
+
+/// What an iterator walks over and how far it has come. Lists and maps are read as they are
+/// when each value is taken, so elements pushed during a loop are visited too.
+#[derive(Debug)]
+enum Cursor {
+    List(Rc<RefCell<List>>, usize),
+    Keys(Rc<RefCell<Map>>, usize),
+    /// A string and the byte offset of its next character.
+    Chars(Rc<str>, usize),
+    Range(Range),
+}
+
+impl Iter {
+    /// Starts iterating over `value`, or returns `None` if it cannot be iterated. An iterator
+    /// continues from where it is.
+    pub fn new(value: &Value) -> Option<Self> {
+        let cursor = match value {
+            Value::List(list) => Cursor::List(list.clone(), 0),
+            Value::Map(map) => Cursor::Keys(map.clone(), 0),
+            Value::String(s) => Cursor::Chars(s.clone(), 0),
+            Value::Range(range) => Cursor::Range(*range),
+            Value::Iterator(iter) => return Some(iter.clone()),
+            _ => return None,
+        };
+        Some(Self(Rc::new(RefCell::new(cursor))))
+    }
+
+    /// The list or map it walks over, if any.
+    pub fn source(&self) -> Option<Value> {
+        match &*self.0.borrow() {
+            Cursor::List(list, _) => Some(Value::List(list.clone())),
+            Cursor::Keys(map, _) => Some(Value::Map(map.clone())),
+            Cursor::Chars(..) | Cursor::Range(_) => None,
+        }
+    }
+
+    /// The address its copies share, and how many copies there are.
+    pub fn identity(&self) -> (usize, usize) {
+        (
+            Rc::as_ptr(&self.0) as *const () as usize,
+            Rc::strong_count(&self.0),
+        )
+    }
+
+    pub fn has_next(&self) -> bool {
+        match &*self.0.borrow() {
+            Cursor::List(list, i) => *i < list.borrow().len(),
+            Cursor::Keys(map, i) => *i < map.borrow().len(),
+            Cursor::Chars(s, i) => *i < s.len(),
+            Cursor::Range(range) => range.len() > 0,
+        }
+    }
+
+    /// Takes the next value, or returns `None` once every value has been taken.
+    pub fn next(&self) -> Option<Value> {
+        match &mut *self.0.borrow_mut() {
+            Cursor::List(list, i) => {
+                let value = list.borrow().get(*i).cloned()?;
+                *i += 1;
+                Some(value)
+            }
+            Cursor::Keys(map, i) => {
+                let key = map.borrow().key(*i).cloned()?;
+                *i += 1;
+                Some(key)
+            }
+            Cursor::Chars(s, i) => {
+                let c = s[*i..].chars().next()?;
+                let value = Value::String(Rc::from(&s[*i..*i + c.len_utf8()]));
+                *i += c.len_utf8();
+                Some(value)
+            }
+            Cursor::Range(range) => {
+                if range.len() == 0 {
+                    return None;
+                }
+                let n = range.start;
+                range.start += 1;
+                Some(Value::Number(n as f64))
+            }
+        }
+    }
+}
+
+impl PartialEq for Iter {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A runtime error caught by a `catch` clause, with the value of the `throw` that raised it,
+/// if any. Copies share the error, and two are equal only when they are the same one.
+#[derive(Debug, Clone)]
+pub struct Caught(Rc<(StrixError, Option<Value>)>);
+
+impl Caught {
+    pub fn new(err: StrixError) -> Self {
+        Self(Rc::new((err, None)))
+    }
+
+    /// The error raised by throwing `value`, which a `catch` clause receives in its place.
+    pub fn thrown(err: StrixError, value: Value) -> Self {
+        Self(Rc::new((err, Some(value))))
+    }
+
+    pub fn error(&self) -> &StrixError {
+        &self.0.0
+    }
+
+    pub fn value(&self) -> Option<&Value> {
+        self.0.1.as_ref()
+    }
+
+    /// The address its copies share, and how many copies there are.
+    pub fn identity(&self) -> (usize, usize) {
+        (
+            Rc::as_ptr(&self.0) as *const () as usize,
+            Rc::strong_count(&self.0),
+        )
+    }
+}
+
+impl PartialEq for Caught {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The hashable form of a map key. Only strings, numbers, bools and nil can be keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    String(Rc<str>),
+    Number(u64),
+    Bool(bool),
+    Nil,
+}
+
+impl HashKey {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(Self::String(s.clone())),
+            // `0` and `-0` are equal, so they must hash the same.
+            Value::Number(n) if *n == 0.0 => Some(Self::Number(0.0f64.to_bits())),
+            Value::Number(n) => Some(Self::Number(n.to_bits())),
+            Value::Bool(b) => Some(Self::Bool(*b)),
+            Value::Nil => Some(Self::Nil),
+            Value::List(_)
+            | Value::Map(_)
+            | Value::Range(_)
+            | Value::Iterator(_)
+            | Value::Error(_) => None,
+        }
+    }
+}
+
+/// A hash map that remembers the order in which its keys were inserted.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    indices: HashMap<HashKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Value> {
+        self.indices.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    /// The key at a position in insertion order.
+    pub fn key(&self, index: usize) -> Option<&Value> {
+        self.entries.get(index).map(|(key, _)| key)
+    }
+
+    pub fn contains_key(&self, key: &HashKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    /// Inserts an entry, keeping the original position when the key already exists.
+    pub fn insert(&mut self, hash: HashKey, key: Value, value: Value) {
+        match self.indices.get(&hash) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indices.insert(hash, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &HashKey) -> Option<Value> {
+        let i = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+
+        for index in self.indices.values_mut() {
+            if *index > i {
+                *index -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        let entries = mem::take(&mut self.entries);
+        dismantle(entries.into_iter().map(|(_, value)| value).collect());
+    }
+}
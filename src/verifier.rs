@@ -0,0 +1,102 @@
+use crate::{
+    chunk::{ASSIGN_OPERATORS, Chunk, DECREMENT, OpCode, PREFIX},
+    err::{ErrorCode, StrixError},
+    messages::{Msg, tr},
+    value::Value,
+};
+
+/// Checks that a chunk loaded from `file` is safe for the `Vm` to run: every reachable
+/// instruction is valid, its operands are in range, jumps land inside the chunk, neither the
+/// stack nor the locals underflow and both have the same depth however an instruction is
+/// reached, and every path ends in `Return` or `Throw`.
+pub fn verify(chunk: &Chunk, file: &str) -> Result<(), StrixError> {
+    let invalid = |reason: String| {
+        StrixError::new(
+            ErrorCode::InvalidBytecode,
+            tr(Msg::InvalidBytecode, &[&file, &reason]),
+        )
+    };
+
+    // The depths of the stack and of the locals before each instruction, once it has been
+    // reached.
+    let mut depths: Vec<Option<(usize, usize)>> = vec![None; chunk.code.len()];
+    let mut pending = vec![(0, 0, 0)];
+
+    while let Some((offset, depth, locals)) = pending.pop() {
+        let Some(&byte) = chunk.code.get(offset) else {
+            return Err(invalid(tr(Msg::RunsPastEnd, &[])));
+        };
+        match depths[offset] {
+            Some(seen) if seen == (depth, locals) => continue,
+            Some(_) => return Err(invalid(tr(Msg::StackMismatch, &[&offset]))),
+            None => depths[offset] = Some((depth, locals)),
+        }
+
+        let Some(op) = OpCode::from_byte(byte) else {
+            return Err(invalid(tr(Msg::BadOpcode, &[&byte, &offset])));
+        };
+        let next = offset + 1 + op.operand_len();
+        if next > chunk.code.len() {
+            return Err(invalid(tr(Msg::RunsPastEnd, &[])));
+        }
+        let u8_operand = || chunk.code[offset + 1];
+        let u16_operand = || chunk.read_u16(offset + 1) as usize;
+        let bad_operand = || invalid(tr(Msg::BadOperand, &[&offset]));
+        let underflow = || invalid(tr(Msg::StackUnderflow, &[&offset]));
+
+        let locals = match op {
+            OpCode::GetLocal | OpCode::ForNext if u16_operand() >= locals => {
+                return Err(bad_operand());
+            }
+            OpCode::PushLocal => locals + 1,
+            OpCode::PopLocal => locals.checked_sub(1).ok_or_else(underflow)?,
+            _ => locals,
+        };
+
+        let valid = match op {
+            OpCode::Constant => u16_operand() < chunk.constants.len(),
+            OpCode::SetIndex => (u8_operand() as usize) < ASSIGN_OPERATORS.len(),
+            OpCode::IncrementIndex => u8_operand() & !(DECREMENT | PREFIX) == 0,
+            OpCode::Invoke => matches!(chunk.constants.get(u16_operand()), Some(Value::String(_))),
+            _ => true,
+        };
+        if !valid {
+            return Err(bad_operand());
+        }
+
+        let (pops, pushes) = op.stack_effect(&chunk.code[offset + 1..next]);
+        let depth = depth.checked_sub(pops).ok_or_else(underflow)? + pushes;
+
+        // Where a jump goes, if the instruction is one. A loop back past the start of the
+        // chunk has no target.
+        let jump = match op {
+            OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::JumpIfNotNil
+            | OpCode::JumpIfNil
+            | OpCode::Try => Some(Some(next + u16_operand())),
+            OpCode::ForNext => Some(Some(next + chunk.read_u16(offset + 3) as usize)),
+            OpCode::Loop => Some(next.checked_sub(u16_operand())),
+            _ => None,
+        };
+        if let Some(target) = jump {
+            match target.filter(|&target| target < chunk.code.len()) {
+                // Only the path that continues a loop gets its next value.
+                Some(target) if op == OpCode::ForNext => {
+                    pending.push((target, depth - pushes, locals))
+                }
+                // A handler starts with the error it caught.
+                Some(target) if op == OpCode::Try => pending.push((target, depth + 1, locals)),
+                Some(target) => pending.push((target, depth, locals)),
+                None => return Err(invalid(tr(Msg::BadJump, &[&offset]))),
+            }
+        }
+
+        match op {
+            OpCode::Throw | OpCode::Return | OpCode::Jump | OpCode::Loop => {}
+            _ => pending.push((next, depth, locals)),
+        }
+    }
+
+    Ok(())
+}
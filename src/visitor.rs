@@ -3,9 +3,82 @@ use crate::{
     tokenizer::{Literal, Token},
 };
 
-pub trait Visitor<T> {
-    fn visit_binary_expr(&mut self, left: &Box<Expr>, operator: &Token, right: &Box<Expr>) -> T;
-    fn visit_grouping_expr(&mut self, expression: &Box<Expr>) -> T;
-    fn visit_literal_expr(&mut self, value: &Literal) -> T;
-    fn visit_unary_expr(&mut self, operator: &Token, right: &Box<Expr>) -> T;
+pub trait Visitor<'a, T> {
+    fn visit_binary_expr(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> T;
+    fn visit_grouping_expr(&mut self, expression: &Expr<'a>) -> T;
+    fn visit_literal_expr(&mut self, value: &Literal<'a>) -> T;
+    fn visit_unary_expr(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> T;
+    fn visit_ternary_expr(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Expr<'a>,
+        else_branch: &Expr<'a>,
+    ) -> T;
+    fn visit_coalesce_expr(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>)
+    -> T;
+    fn visit_range_expr(&mut self, start: &Expr<'a>, operator: &Token<'a>, end: &Expr<'a>) -> T;
+    fn visit_list_expr(&mut self, bracket: &Token<'a>, elements: &[Expr<'a>]) -> T;
+    fn visit_map_expr(&mut self, brace: &Token<'a>, entries: &[(Expr<'a>, Expr<'a>)]) -> T;
+    fn visit_index_expr(&mut self, object: &Expr<'a>, bracket: &Token<'a>, index: &Expr<'a>) -> T;
+    fn visit_slice_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        start: Option<&Expr<'a>>,
+        end: Option<&Expr<'a>>,
+    ) -> T;
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        operator: &Token<'a>,
+        value: &Expr<'a>,
+    ) -> T;
+    fn visit_increment_index_expr(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        operator: &Token<'a>,
+        prefix: bool,
+    ) -> T;
+    fn visit_invoke_expr(
+        &mut self,
+        object: &Expr<'a>,
+        name: &Token<'a>,
+        arguments: &[Expr<'a>],
+        optional: bool,
+    ) -> T;
+    fn visit_throw_expr(&mut self, keyword: &Token<'a>, value: &Expr<'a>) -> T;
+    fn visit_variable_expr(&mut self, name: &Token<'a>, slot: usize) -> T;
+    fn visit_block_expr(&mut self, brace: &Token<'a>, expressions: &[Expr<'a>]) -> T;
+    fn visit_for_expr(
+        &mut self,
+        label: Option<&Token<'a>>,
+        keyword: &Token<'a>,
+        variable: &Token<'a>,
+        iterable: &Expr<'a>,
+        body: &Expr<'a>,
+        collect: bool,
+    ) -> T;
+    fn visit_break_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        label: Option<&Token<'a>>,
+        loops: usize,
+    ) -> T;
+    fn visit_continue_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        label: Option<&Token<'a>>,
+        loops: usize,
+    ) -> T;
+    fn visit_try_expr(
+        &mut self,
+        keyword: &Token<'a>,
+        body: &Expr<'a>,
+        handler: Option<(&Token<'a>, &Expr<'a>)>,
+        finally: Option<&Expr<'a>>,
+    ) -> T;
 }
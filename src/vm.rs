@@ -0,0 +1,422 @@
+use crate::{
+    chunk::{ASSIGN_OPERATORS, Chunk, DECREMENT, OpCode, PREFIX},
+    disasm,
+    err::{Span, StrixError},
+    gc::{self, GcStats, Heap},
+    interpreter::{self, CallStack},
+    tokenizer::{Token, TokenType},
+    value::{Caught, Map, Value},
+};
+
+/// Runs compiled chunks on a value stack. Produces the same results and errors as the
+/// tree-walking `Interpreter`, without recursing on the native stack.
+pub struct Vm {
+    calls: CallStack,
+    stack: Vec<Value>,
+    /// The local variables in scope, by slot.
+    locals: Vec<Value>,
+    /// The `try` expressions running, innermost last.
+    handlers: Vec<Handler>,
+    /// The error that the last `Throw` raised, with the value thrown, until it is caught.
+    thrown: Option<Caught>,
+    /// Whether to print each instruction and the stack before running it.
+    trace_exec: bool,
+    heap: Heap,
+}
+
+impl Vm {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::with_file("<script>".to_string())
+    }
+
+    pub fn with_file(file: String) -> Self {
+        Self {
+            calls: CallStack::new(file),
+            stack: vec![],
+            locals: vec![],
+            handlers: vec![],
+            thrown: None,
+            trace_exec: false,
+            heap: Heap::new(),
+        }
+    }
+
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.calls.max_depth = max_call_depth;
+    }
+
+    pub fn set_trace_exec(&mut self, trace_exec: bool) {
+        self.trace_exec = trace_exec;
+    }
+
+    pub fn set_max_heap(&mut self, max_heap: Option<usize>) {
+        self.heap.set_max_bytes(max_heap);
+    }
+
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.heap.set_stress(gc_stress);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> Result<Value, StrixError> {
+        self.calls.reset();
+        self.stack.clear();
+        self.locals.clear();
+        self.handlers.clear();
+        self.thrown = None;
+
+        self.execute(chunk).map_err(|err| self.calls.trace(err))
+    }
+
+    fn execute(&mut self, chunk: &Chunk) -> Result<Value, StrixError> {
+        let mut ip = 0;
+
+        loop {
+            if self.trace_exec {
+                self.trace(chunk, ip);
+            }
+
+            match self.step(chunk, &mut ip) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(err) => ip = self.catch(err)?,
+            }
+        }
+    }
+
+    /// Runs the instruction at `ip` and moves `ip` to the next one to run, returning the
+    /// script's value once it returns.
+    fn step(&mut self, chunk: &Chunk, ip: &mut usize) -> Result<Option<Value>, StrixError> {
+        let offset = *ip;
+        let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+            unreachable!("invalid opcode at offset {offset}");
+        };
+        *ip += 1 + op.operand_len();
+
+        match op {
+            OpCode::Constant => {
+                let index = chunk.read_u16(offset + 1) as usize;
+                self.stack.push(chunk.constants[index].clone());
+            }
+            OpCode::Nil => self.stack.push(Value::Nil),
+            OpCode::True => self.stack.push(Value::Bool(true)),
+            OpCode::False => self.stack.push(Value::Bool(false)),
+            OpCode::Pop => {
+                self.pop();
+            }
+
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Modulo
+            | OpCode::BitAnd
+            | OpCode::BitOr
+            | OpCode::BitXor
+            | OpCode::ShiftLeft
+            | OpCode::ShiftRight
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Equal
+            | OpCode::NotEqual => {
+                let token_type = match op {
+                    OpCode::Add => TokenType::Plus,
+                    OpCode::Subtract => TokenType::Minus,
+                    OpCode::Multiply => TokenType::Star,
+                    OpCode::Divide => TokenType::Slash,
+                    OpCode::Modulo => TokenType::Percent,
+                    OpCode::BitAnd => TokenType::Ampersand,
+                    OpCode::BitOr => TokenType::Pipe,
+                    OpCode::BitXor => TokenType::Caret,
+                    OpCode::ShiftLeft => TokenType::LessLess,
+                    OpCode::ShiftRight => TokenType::GreaterGreater,
+                    OpCode::Greater => TokenType::Greater,
+                    OpCode::GreaterEqual => TokenType::GreaterEqual,
+                    OpCode::Less => TokenType::Less,
+                    OpCode::LessEqual => TokenType::LessEqual,
+                    OpCode::Equal => TokenType::EqualEqual,
+                    _ => TokenType::BangEqual,
+                };
+                let right = self.pop();
+                let left = self.pop();
+                let operator = token(token_type, chunk.span_at(offset));
+                self.stack
+                    .push(interpreter::binary_op(&operator, left, right)?);
+            }
+            OpCode::Negate | OpCode::Not => {
+                let token_type = if op == OpCode::Negate {
+                    TokenType::Minus
+                } else {
+                    TokenType::Bang
+                };
+                let right = self.pop();
+                let operator = token(token_type, chunk.span_at(offset));
+                self.stack.push(interpreter::unary_op(&operator, right)?);
+            }
+
+            OpCode::Jump => *ip += chunk.read_u16(offset + 1) as usize,
+            OpCode::JumpIfFalse => {
+                if !interpreter::is_truthy(self.peek()) {
+                    *ip += chunk.read_u16(offset + 1) as usize;
+                }
+            }
+            OpCode::JumpIfNotNil => {
+                if *self.peek() != Value::Nil {
+                    *ip += chunk.read_u16(offset + 1) as usize;
+                }
+            }
+            OpCode::JumpIfNil => {
+                if *self.peek() == Value::Nil {
+                    *ip += chunk.read_u16(offset + 1) as usize;
+                }
+            }
+            OpCode::Loop => *ip -= chunk.read_u16(offset + 1) as usize,
+
+            OpCode::Range | OpCode::RangeInclusive => {
+                let token_type = if op == OpCode::RangeInclusive {
+                    TokenType::DotDotEqual
+                } else {
+                    TokenType::DotDot
+                };
+                let end = self.pop();
+                let start = self.pop();
+                let operator = token(token_type, chunk.span_at(offset));
+                self.stack.push(interpreter::range(&operator, start, end)?);
+            }
+            OpCode::List => {
+                let count = chunk.read_u16(offset + 1) as usize;
+                let elements = self.stack.split_off(self.stack.len().saturating_sub(count));
+                self.stack.push(Value::new_list(elements));
+                self.allocate(chunk.span_at(offset))?;
+            }
+            OpCode::Map => {
+                let count = chunk.read_u16(offset + 1) as usize;
+                let entries = self
+                    .stack
+                    .split_off(self.stack.len().saturating_sub(count * 2));
+                let brace = token(TokenType::LeftBrace, chunk.span_at(offset));
+
+                let mut map = Map::new();
+                let mut entries = entries.into_iter();
+                while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                    map.insert(interpreter::hash_key(&brace, &key)?, key, value);
+                }
+                self.stack.push(Value::new_map(map));
+                self.allocate(chunk.span_at(offset))?;
+            }
+            OpCode::Index => {
+                let index = self.pop();
+                let object = self.pop();
+                let bracket = token(TokenType::LeftBracket, chunk.span_at(offset));
+                self.stack
+                    .push(interpreter::get_index(&bracket, &object, &index)?);
+            }
+            OpCode::Slice => {
+                let end = self.pop();
+                let start = self.pop();
+                let object = self.pop();
+                let bracket = token(TokenType::LeftBracket, chunk.span_at(offset));
+                self.stack
+                    .push(interpreter::slice(&bracket, &object, &start, &end)?);
+                self.allocate(chunk.span_at(offset))?;
+            }
+            OpCode::SetIndex => {
+                let position = chunk.code[offset + 1] as usize;
+                let value = self.pop();
+                let index = self.pop();
+                let object = self.pop();
+                let bracket = token(TokenType::LeftBracket, chunk.span_at(offset));
+                let operator = token(ASSIGN_OPERATORS[position], chunk.span_at(offset + 1));
+                // Assigning to a new key grows a map.
+                let before = gc::size(&object);
+                self.stack.push(interpreter::assign_index(
+                    &bracket, &object, index, &operator, value,
+                )?);
+                self.resized(&object, before, chunk.span_at(offset))?;
+            }
+            OpCode::IncrementIndex => {
+                let flags = chunk.code[offset + 1];
+                let index = self.pop();
+                let object = self.pop();
+                let bracket = token(TokenType::LeftBracket, chunk.span_at(offset));
+                let token_type = if flags & DECREMENT != 0 {
+                    TokenType::MinusMinus
+                } else {
+                    TokenType::PlusPlus
+                };
+                let operator = token(token_type, chunk.span_at(offset + 1));
+                self.stack.push(interpreter::increment_index(
+                    &bracket,
+                    &object,
+                    index,
+                    &operator,
+                    flags & PREFIX != 0,
+                )?);
+            }
+            OpCode::Invoke => {
+                let constant = chunk.read_u16(offset + 1) as usize;
+                let count = chunk.code[offset + 3] as usize;
+                let args = self.stack.split_off(self.stack.len().saturating_sub(count));
+                let object = self.pop();
+
+                // The verifier guarantees a string here, which the name borrows.
+                let method = match &chunk.constants[constant] {
+                    Value::String(method) => method.as_ref(),
+                    _ => "",
+                };
+                let name =
+                    Token::spanned(TokenType::Identifier, method, None, chunk.span_at(offset));
+
+                // Methods such as `push` and `insert` grow the list or map they are called on.
+                let receiver = object.clone();
+                let before = gc::size(&receiver);
+                self.calls.enter(&object, &name)?;
+                let result = interpreter::call_method(object, &name, args)
+                    .map_err(|err| self.calls.trace(err));
+                self.calls.exit();
+                self.resized(&receiver, before, chunk.span_at(offset))?;
+                self.stack.push(result?);
+                self.allocate(chunk.span_at(offset))?;
+            }
+            OpCode::GetLocal => {
+                let slot = chunk.read_u16(offset + 1) as usize;
+                let value = self.locals.get(slot).cloned().unwrap_or(Value::Nil);
+                self.stack.push(value);
+            }
+            OpCode::PushLocal => {
+                let value = self.pop();
+                self.locals.push(value);
+            }
+            OpCode::PopLocal => {
+                self.locals.pop();
+            }
+            OpCode::Iter => {
+                let value = self.pop();
+                let keyword = token(TokenType::For, chunk.span_at(offset));
+                self.stack
+                    .push(Value::Iterator(interpreter::iter(&keyword, &value)?));
+            }
+            OpCode::ForNext => {
+                let slot = chunk.read_u16(offset + 1) as usize;
+                let next = match self.locals.get(slot) {
+                    Some(Value::Iterator(iter)) => iter.next(),
+                    _ => None,
+                };
+                match next {
+                    Some(value) => self.stack.push(value),
+                    None => *ip += chunk.read_u16(offset + 3) as usize,
+                }
+            }
+            OpCode::Append => {
+                let value = self.pop();
+                let before = gc::size(self.peek());
+                if let Value::List(list) = self.peek() {
+                    list.borrow_mut().push(value);
+                }
+                let list = self.peek().clone();
+                self.resized(&list, before, chunk.span_at(offset))?;
+            }
+            OpCode::Try => self.handlers.push(Handler {
+                ip: *ip + chunk.read_u16(offset + 1) as usize,
+                stack: self.stack.len(),
+                locals: self.locals.len(),
+            }),
+            OpCode::EndTry => {
+                self.handlers.pop();
+            }
+            OpCode::Caught => {
+                let value = match self.pop() {
+                    Value::Error(caught) => interpreter::caught(caught),
+                    value => value,
+                };
+                self.stack.push(value);
+            }
+            OpCode::Throw => {
+                let value = self.pop();
+                let keyword = token(TokenType::Throw, chunk.span_at(offset));
+                let thrown = interpreter::throw(&keyword, value);
+                let err = thrown.error().clone();
+                self.thrown = Some(thrown);
+                return Err(err);
+            }
+            OpCode::Return => return Ok(Some(self.pop())),
+        }
+
+        Ok(None)
+    }
+
+    /// Hands an error to the innermost handler, if it can be caught, returning where the
+    /// handler's code starts. The handler gets the stack and the locals as they were when its
+    /// `Try` ran, with the error pushed.
+    fn catch(&mut self, err: StrixError) -> Result<usize, StrixError> {
+        // An error from a `Throw` is caught with the value thrown.
+        let caught = self.thrown.take();
+        if !err.is_catchable() {
+            return Err(err);
+        }
+        let Some(handler) = self.handlers.pop() else {
+            return Err(err);
+        };
+
+        self.stack.truncate(handler.stack);
+        self.locals.truncate(handler.locals);
+        let caught = caught.unwrap_or_else(|| Caught::new(err));
+        self.stack.push(Value::Error(caught));
+        Ok(handler.ip)
+    }
+
+    fn trace(&self, chunk: &Chunk, offset: usize) {
+        let stack: String = self
+            .stack
+            .iter()
+            .map(|value| format!("[ {} ]", disasm::show(value)))
+            .collect();
+        eprintln!("          {stack}");
+        eprintln!("{}", disasm::instruction(chunk, offset).0);
+    }
+
+    /// Hands the value on top of the stack, which the last instruction may have just created,
+    /// to the heap. Between instructions the VM holds every value on its stack or in its
+    /// locals, so the stack is the root set, and the heap finds the locals as references from
+    /// outside it.
+    fn allocate(&mut self, span: Span) -> Result<(), StrixError> {
+        if let Some(value) = self.stack.last() {
+            self.heap.track(value);
+        }
+        self.heap.collect_if_needed(&self.stack, span)
+    }
+
+    /// Tells the heap that a list or map that measured `before` bytes may have changed size.
+    fn resized(&mut self, value: &Value, before: usize, span: Span) -> Result<(), StrixError> {
+        self.heap.resize(value, before);
+        self.heap.collect_if_needed(&self.stack, span)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().unwrap_or(&Value::Nil)
+    }
+}
+
+/// Where to continue when a `try` expression catches an error, and how deep the stack and the
+/// locals were when it started.
+struct Handler {
+    ip: usize,
+    stack: usize,
+    locals: usize,
+}
+
+/// Recreates the token an instruction was compiled from, for the operations shared with the
+/// `Interpreter`. Only method names need a lexeme.
+fn token(token_type: TokenType, span: Span) -> Token<'static> {
+    Token::spanned(token_type, "", None, span)
+}